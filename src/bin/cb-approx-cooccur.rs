@@ -1,12 +1,80 @@
 //! Example 0: Word cooccurrence counter
 //!
 //! This simple script takes an input WET corpus piped to STDIN and counts cooccurrences of input
-//! tokens, where tokens are defined by unicode, and cooccurrence is a window 21 words in
-//! diameter, and only words from a newline-separated list are considered.
+//! tokens, where tokens are defined by unicode, and cooccurrence is a window sliding over every
+//! token, by default reaching 10 tokens to either side (--radius), and only words from a
+//! newline-separated list are considered.
+//!
+//! The whitespace between tokens isn't a token, so it takes up no room in windows (see
+//! `cabarrus::tokens`).
 //!
 //! The output is a numpy file with a cooccurrence matrix. The rows are the center word, starting
 //! with the unknown word as word 0, and proceeding in the order they were specified in the input
-//! word list. The columns are the context words, and cooccurrence is always counted as 1 or 0.
+//! word list. The columns are the context words, and each time a context word is in a center
+//! word's window adds its weight: 1 by default, or decaying with distance (--weighting). The
+//! window can reach further to one side than the other (--left-radius, --right-radius), and a
+//! word is not its own context unless --include-self is given. By default windows run across
+//! whole documents, but they can be kept within paragraphs or sentences (--boundary).
+//!
+//! Contexts can also be told apart by side (--contexts=directional) or by offset
+//! (--contexts=positional). Then every word has a random context vector for each side or offset,
+//! in blocks laid out like the rows, which is how --context dumps them. The layout is recorded in
+//! a JSON file next to the output (output.npy.json).
+//!
+//! Rather than a column for every context word, every context word has a random vector, and each
+//! row adds up the vectors of its contexts (random indexing, see `cabarrus::randindex`). The
+//! vectors have 1024 dimensions unless --rank says otherwise, and can be drawn from a normal
+//! (the default), uniform or sparse ternary (--distribution, with --fill) distribution, then
+//! normalized (--normalization). The same --seed always gives the same vectors. Given them (from
+//! --context), cb-recover estimates the counts back, with how far off they might be.
+//!
+//! Word order is lost unless the context vectors are rotated by where they are (--permutation):
+//! by side (direction) or by offset. Then cb-neighbours can tell which words are likely to come
+//! before or after a word.
+//!
+//! The counts can be made to reach further with reflective random indexing (--cycles): each cycle
+//! after the first goes over the corpus again with every word's row from the last cycle, at unit
+//! length, as its context vector, so words come to resemble words that share their contexts
+//! without ever being seen together. Cycles can also alternate through documents (--alternate,
+//! Cohen et al., 2010): each document, or whatever --boundary splits the corpus into, adds up its
+//! words' vectors, and each word then adds up its documents'. The corpus is kept tokenized in
+//! output.tokens for later cycles, and every cycle but the last is saved as output.cycleN, with its
+//! JSON file.
+//!
+//! New input can be counted into an existing output with --update, which adds to the matrix in
+//! place, as long as its JSON file shows it was made with the same word list, tokenizing, window
+//! and context vectors (including the --seed). Each input has to be named with --input-name,
+//! which is recorded there so that the same one is never counted twice. This doesn't work with
+//! --context, --open or --cycles.
+//!
+//! Ternary vectors are kept as only their nonzero entries unless they are QR normalized, so with
+//! a small --fill (try 0.01, about 10 nonzeros in 1024) each context touches only a few cells of
+//! its row. The counts come out the same either way (see the tests of `cabarrus::randindex`);
+//! --dense adds whole vectors instead, and the throughput in tokens per second is logged for
+//! comparison. cb-exp-sparse-vectors measures the difference over a range of fills and ranks.
+//!
+//! Before any windows are formed, stopwords can be left out (--stopwords) and frequent words can
+//! be randomly discarded like in word2vec (--subsample, which needs a word list with counts, as
+//! made by cb-vocab). Both shrink the distances between the remaining words. Phrases learned by
+//! cb-phrases can be merged into single tokens first (--phrases).
+//!
+//! Words not in the word list are kept as the unknown word 0 by default. They can instead be
+//! dropped before windows are formed (--unknowns=drop), like word2vec, or skipped, taking up room
+//! in windows without being counted (--unknowns=skip). The rate of unknown words is logged and
+//! recorded in the JSON file next to the output.
+//!
+//! For an open vocabulary, words can instead be split into subwords (--subwords, with merges made
+//! by cb-bpe). Then the word list has to be the subword list cb-bpe wrote with them, and the rows
+//! and columns are subwords.
+//!
+//! Or the vocabulary can be left fully open (--open=FILE). Then every token's context vector is
+//! derived from its hash, so no table of them is kept, and every token seen gets a row, most
+//! frequent first after the unknown row 0. The tokens and their counts are written to FILE as the
+//! word list of the rows. Rows take rank * 8 bytes each, and crawled text has no end of distinct
+//! tokens, so only the first --max-rows tokens seen (100000 by default) get rows, and any new ones
+//! after that are counted in row 0, like unknown words. The word list argument is still read, for
+//! --subsample and --unknowns=drop, but it may be empty. Hashed vectors can't be QR normalized,
+//! and --cycles, --dense and --unknowns=skip don't apply, so they are refused.
 //!
 
 // argument parsing
@@ -16,30 +84,23 @@
 extern crate env_logger;
 // numpy-like arrays
 extern crate ndarray;
-extern crate ndarray_rand;
-extern crate rand;
-// better segmentation
-extern crate unicode_segmentation;
 // lastly, this library
 extern crate cabarrus;
 
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::cmp::min;
-use std::collections::HashMap;
+use std::fs;
+use std::time::Instant;
 use ndarray::prelude::*;
-use ndarray_rand::RandomExt;
-use rand::SeedableRng;
-use rand::distributions::Normal;
-use unicode_segmentation::UnicodeSegmentation;
 
 use cabarrus::warc::WarcStreamer;
 use cabarrus::errors::*;
+use cabarrus::farm::{FarmMap, new_farm};
+use cabarrus::meta::{Block, MatrixMeta};
 use cabarrus::numpy;
-
-const RANK: usize = 1024;
-const WINDOW_RADIUS: usize = 10;
-const WINDOW_WIDTH: usize = 2 * WINDOW_RADIUS + 1;
+use cabarrus::options;
+use cabarrus::randindex::{IndexVectors, RandomIndex};
+use cabarrus::tokens::{TokenCacheReader, TokenCacheWriter, Unknowns};
+use cabarrus::vocab::Vocab;
+use cabarrus::window::{Contexts, Window};
 
 pub fn main() {
     // Main can't return a Result, and the ? operator needs the enclosing function to return Result
@@ -49,74 +110,308 @@ pub fn inner_main() -> Result<()> {
     env_logger::init().unwrap();
     let args = app_from_crate!()
         .arg_from_usage("--context 'get the random (but consistent) context vectors instead of counting")
+        .arg_from_usage("--dense 'add whole context vectors even when they could be sparse (slower, for comparison)'")
+        .arg_from_usage("--cycles=[N] 'count N times, each time using the last counts as the context vectors (default 1)'")
+        .arg_from_usage("--alternate 'in cycles after the first, go through documents instead of windows'")
+        .arg_from_usage("--open=[FILE] 'give every token a row and a hashed context vector, writing the tokens seen to FILE'")
+        .arg_from_usage("--max-rows=[N] 'with --open, how many tokens can have rows, after which new ones are unknown (default 100000)'")
+        .args_from_usage(options::PHRASES)
+        .args_from_usage(options::TOKENIZER)
+        .args_from_usage(options::WINDOW)
+        .args_from_usage(options::RANDOM_INDEX)
+        .args_from_usage(options::UPDATE)
         .arg_from_usage("<wordlist> 'file containing words to look for, one per line'")
         .arg_from_usage("<output> 'file in which to store the resulting cooccurrence matrix'")
         .get_matches();
 
     // Read the word list from a file.
-    let mut words = vec![];
-    for line in BufReader::new(File::open(args.value_of("wordlist").unwrap())?).lines() {
-        words.push(line?);
-    }
-    
-    // Note that word 0 is the unknown word.
-    let word_ids: HashMap<&str, usize> = words.iter()
-        .enumerate()
-        .map(|(id, word)| (word.as_ref(), id + 1))
-        .collect();
-
-    // Contexts is a random of uniformly distributed but deterministic floats which are almost
-    // orthogonal vectors representing each word
+    let mut tokenizer = options::tokenizer(&args, Vocab::read(args.value_of("wordlist").unwrap())?)?;
+    let vocab_size = tokenizer.vocab().len();
+    let window = options::window(&args)?;
+    let output = args.value_of("output").unwrap();
+
+    // Contexts is a random but deterministic matrix of nearly orthogonal vectors representing
+    // each word
     // Cooccurrences is an accumulator of those context vectors
-    let mut rng = rand::StdRng::from_seed(&[3141592653589793]);
-    let contexts: Array2<f64> = Array::random_using([words.len()+ 1, RANK],
-        Normal::new(0., 1.),
-        &mut rng);
-    let mut cooccurrences: Array2<f64> = Array2::zeros([words.len() + 1, RANK]);
-    
+    // There is a block of context vectors for every side or offset the window tells apart
+    let blocks = window.blocks();
+    let random_index = options::random_index(&args)?;
+    let rank = random_index.rank;
+    if args.is_present("update") && (args.is_present("context") || args.is_present("open")) {
+        return Err(Error::Other("Only counts over a fixed word list can be added to.".to_owned()));
+    }
     if args.is_present("context") {
         // Just dump the contexts (not the usual way you'd use this program)
-        numpy::write_matrix(args.value_of("output").unwrap(), &contexts)?;
+        let contexts = random_index.vectors(blocks.len() * (vocab_size + 1))?;
+        numpy::write_matrix(output, &contexts)?;
+        let mut meta = MatrixMeta::new("cb-approx-cooccur", contexts.rows(), contexts.cols());
+        meta.wordlist = args.value_of("wordlist").map(String::from);
+        meta.row_blocks = Block::tile(&blocks, vocab_size + 1);
+        meta.column_blocks = Block::tile(&["random dimension".to_owned()], rank);
+        meta.window = Some(window);
+        meta.random_index = Some(random_index);
+        meta.write_for(output)?;
+    } else if let Some(open_path) = args.value_of("open") {
+        // Every token's context vectors are hashed, and only ever added sparsely where they can be
+        for option in ["cycles", "alternate", "dense"].iter() {
+            if args.is_present(option) {
+                return Err(Error::Other(format!("--{} can't be used with --open.", option)));
+            }
+        }
+        if tokenizer.unknowns() == Unknowns::Skip {
+            return Err(Error::Other("Every token has a row with --open, so there are no unknown \
+                words to skip.".to_owned()));
+        }
+
+        // Any token can have a row, so the rows are found as they come, along with their counts.
+        // Row 0 is the unknown word, as usual, for the tokens that come after the rows run out.
+        let max_rows = options::value(&args, "max-rows")?.unwrap_or(100000);
+        info!("Collecting cooccurrences of every token, with hashed context vectors.");
+        let mut ids: FarmMap<String, usize> = new_farm();
+        let mut names = vec![String::new()];
+        let mut counts: Vec<u64> = vec![0];
+        let mut rows: Vec<Array1<f64>> = vec![Array1::zeros(rank)];
+        for rec in WarcStreamer::new()? {
+            for segment in window.boundary.split(&rec) {
+                let words = tokenizer.tokenize_words(segment);
+                let mut mention_ids = Vec::with_capacity(words.len());
+                for word in words.iter() {
+                    let id = match ids.get(word) {
+                        Some(&id) => id,
+                        // Out of rows, so it's counted as the unknown word
+                        None if rows.len() > max_rows => 0,
+                        None => {
+                            ids.insert(word.clone(), rows.len());
+                            names.push(word.clone());
+                            counts.push(0);
+                            rows.push(Array1::zeros(rank));
+                            rows.len() - 1
+                        }
+                    };
+                    counts[id] += 1;
+                    mention_ids.push(id);
+                }
+                // Each distinct token's context vectors, made once per segment rather than once
+                // per occurrence (keyed by token, since all tokens past --max-rows share row 0)
+                let mut slots: FarmMap<&str, usize> = new_farm();
+                let mut vectors: Vec<Vec<Vec<(usize, f64)>>> = vec![];
+                let mut mention_slots = Vec::with_capacity(words.len());
+                for word in words.iter() {
+                    if !slots.contains_key(word.as_str()) {
+                        slots.insert(word, vectors.len());
+                        vectors.push((0..blocks.len())
+                            .map(|block| random_index.hashed_vector(word, block))
+                            .collect::<Result<Vec<_>>>()?);
+                    }
+                    mention_slots.push(slots[word.as_str()]);
+                }
+                window.for_each_pair(words.len(), |mention_i, context_i, weight| {
+                    let row = &mut rows[mention_ids[mention_i]];
+                    let rotation = random_index.rotation(context_i as isize - mention_i as isize);
+                    let block = window.block(mention_i, context_i);
+                    for &(dimension, value) in vectors[mention_slots[context_i]][block].iter() {
+                        row[(dimension + rotation) % rank] += weight * value;
+                    }
+                });
+            }
+        }
+        info!("Found {} distinct tokens in {}.", rows.len() - 1, tokenizer.looked_up());
+        info!("{:.2}% of {} tokens were not in the word list ({:?}).",
+            100.0 * tokenizer.unknown_rate(), tokenizer.looked_up(), tokenizer.unknowns());
+        // These aren't unknown to the word list, so they're only logged, not in unknown_rate
+        if counts[0] > 0 {
+            warn!("{} tokens ({:.2}%) came after all {} rows were taken, and were counted as the \
+                unknown word. Raise --max-rows to give them rows.", counts[0],
+                100.0 * counts[0] as f64 / counts.iter().sum::<u64>() as f64, max_rows);
+        }
+
+        // Most frequent first, and alphabetical among equals, so the order is repeatable
+        let mut order: Vec<usize> = (1..rows.len()).collect();
+        order.sort_by(|&a, &b| counts[b].cmp(&counts[a]).then_with(|| names[a].cmp(&names[b])));
+        order.insert(0, 0);
+        {
+            let outfile = numpy::create_empty_mmap(output, &[rows.len(), rank])?;
+            let mut cooccurrences = numpy::read_matrix_mmap(&outfile)?;
+            for (i, &id) in order.iter().enumerate() {
+                cooccurrences.row_mut(i).assign(&rows[id]);
+            }
+        }
+        let height = rows.len();
+        let words = order[1..].iter().map(|&id| names[id].clone()).collect();
+        let counts = order[1..].iter().map(|&id| counts[id]).collect();
+        Vocab::new(words, Some(counts)).write(open_path)?;
+
+        let mut meta = MatrixMeta::new("cb-approx-cooccur", height, rank);
+        meta.wordlist = Some(open_path.to_owned());
+        meta.tokenizer = Some(tokenizer.settings());
+        meta.row_blocks = Block::tile(&["center word".to_owned()], height);
+        meta.column_blocks = Block::tile(&["random projection of hashed contexts".to_owned()], rank);
+        meta.window = Some(window);
+        meta.random_index = Some(RandomIndex { hashed: true, ..random_index });
+        meta.tokens = Some(tokenizer.looked_up());
+        meta.unknown_rate = Some(tokenizer.unknown_rate());
+        meta.write_for(output)?;
     } else {
         // The usual case: count the words' cooccurrences
-        if words.len() < 25 {
-            info!("Collecting cooccurrences (with one another) of: {:?}", words);
+        let cycles = options::value(&args, "cycles")?.unwrap_or(1);
+        let alternate = args.is_present("alternate");
+        if cycles < 1 {
+            return Err(Error::Other("There has to be at least one cycle.".to_owned()));
+        }
+        if cycles > 1 && args.is_present("update") {
+            return Err(Error::Other("Reflective cycles start over from the corpus each time, so \
+                they can't be added to.".to_owned()));
+        }
+        if cycles > 1 && window.contexts != Contexts::Plain {
+            return Err(Error::Other("Reflective cycles use each word's row as its context vector, \
+                so there's only one for every side and offset. Use --permutation to keep word \
+                order instead of --contexts.".to_owned()));
+        }
+        let mut contexts = random_index.index_vectors(blocks.len() * (vocab_size + 1),
+            args.is_present("dense"))?;
+        if let IndexVectors::Sparse(ref sparse) = contexts {
+            info!("Keeping the context vectors sparse, with {:.1} nonzeros each on average.",
+                sparse.nnz() as f64 / sparse.rows() as f64);
+        }
+        if vocab_size < 25 {
+            info!("Collecting cooccurrences (with one another) of: {:?}", tokenizer.vocab().words());
         } else {
-            info!("Collecting cooccurrences (with one another) of {} words.", words.len());
+            info!("Collecting cooccurrences (with one another) of {} words.", vocab_size);
         }
 
-        for rec in WarcStreamer::new()? {
-            let mention_ids = tokenize(&rec, &word_ids);
-            for mention_i in 0..mention_ids.len() {
-                for context_i in mention_i..min(mention_ids.len(), WINDOW_WIDTH) {
-                    cooccurrences
-                    .row_mut(mention_ids[mention_i]) // row: center word
-                    .scaled_add(1.0, // uniform window weight
-                        &contexts.row(mention_ids[context_i])); // column: context word;
+        let mut meta = MatrixMeta::new("cb-approx-cooccur", vocab_size + 1, rank);
+        meta.wordlist = args.value_of("wordlist").map(String::from);
+        meta.vocabulary = Some(tokenizer.vocab().fingerprint());
+        meta.tokenizer = Some(tokenizer.settings());
+        meta.row_blocks = Block::tile(&["center word".to_owned()], vocab_size + 1);
+        meta.column_blocks = Block::tile(&["random projection of contexts".to_owned()], rank);
+        meta.window = Some(window.clone());
+        meta.random_index = Some(random_index.clone());
+        let mut existing = options::update(&args, output, &mut meta)?;
+        let outfile = match existing {
+            Some(_) => Some(numpy::open_matrix_mmap(output)?),
+            None => None
+        };
+
+        // Later cycles go over the corpus again, so the first keeps its tokens
+        let skip_unknowns = tokenizer.unknowns() == Unknowns::Skip;
+        let cache_path = format!("{}.tokens", output);
+        for cycle in 1..cycles + 1 {
+            // An update needs no matrix besides the output, so this one is left empty then
+            let height = if outfile.is_some() { 0 } else { vocab_size + 1 };
+            let mut cooccurrences: Array2<f64> = Array2::zeros([height, rank]);
+            let start = Instant::now();
+            let counted = {
+                // When adding to the output, the counts go straight into it
+                let mut target = match outfile {
+                    Some(ref outfile) => numpy::read_matrix_mmap(outfile)?,
+                    None => cooccurrences.view_mut()
+                };
+                let mut counted = 0;
+                if cycle == 1 {
+                    let mut cache = if cycles > 1 { Some(TokenCacheWriter::create(&cache_path)?) } else { None };
+                    for rec in WarcStreamer::new()? {
+                        for segment in window.boundary.split(&rec) {
+                            let mention_ids = tokenizer.tokenize(segment);
+                            if let Some(ref mut cache) = cache {
+                                cache.push(&mention_ids)?;
+                            }
+                            counted += mention_ids.len();
+                            count_segment(&mention_ids, &window, &random_index, &contexts,
+                                &mut target, skip_unknowns);
+                        }
+                    }
+                    if let Some(cache) = cache {
+                        cache.finish()?;
+                    }
+                } else {
+                    for mention_ids in TokenCacheReader::open(&cache_path)? {
+                        let mention_ids = mention_ids?;
+                        counted += mention_ids.len();
+                        if alternate {
+                            reflect_segment(&mention_ids, &contexts, &mut target, skip_unknowns);
+                        } else {
+                            count_segment(&mention_ids, &window, &random_index, &contexts,
+                                &mut target, skip_unknowns);
+                        }
+                    }
+                }
+                counted
+            };
+            let elapsed = start.elapsed();
+            let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+            info!("Cycle {} of {}: counted {} tokens in {:.2}s ({:.0} tokens/s, {} context vectors).",
+                cycle, cycles, counted, seconds, counted as f64 / seconds,
+                if let IndexVectors::Sparse(_) = contexts { "sparse" } else { "dense" });
+            if cycle == 1 {
+                info!("{:.2}% of {} tokens were not in the word list ({:?}).",
+                    100.0 * tokenizer.unknown_rate(), tokenizer.looked_up(), tokenizer.unknowns());
+            }
+            if cycle == cycles && vocab_size <= 10 && outfile.is_none() {
+                println!("Cooccurrences look like {}", cooccurrences);
+            }
+
+            // Every cycle is saved, the last as the output
+            let path = if cycle == cycles { output.to_owned() } else { format!("{}.cycle{}", output, cycle) };
+            if outfile.is_none() {
+                numpy::write_matrix(&path, &cooccurrences)?;
+            }
+            let mut meta = meta.clone();
+            meta.tokens = Some(tokenizer.looked_up());
+            meta.unknown_rate = Some(tokenizer.unknown_rate());
+            meta.cycles = if cycles > 1 { Some(cycle) } else { None };
+            if let Some(existing) = existing.take() {
+                meta.absorb(existing);
+            }
+            meta.write_for(&path)?;
+
+            // Reflect: the rows, scaled to unit length, are the next cycle's context vectors
+            if cycle < cycles {
+                for mut row in cooccurrences.outer_iter_mut() {
+                    let norm = row.dot(&row).sqrt();
+                    if norm > 0.0 { row /= norm; }
                 }
+                contexts = IndexVectors::Dense(cooccurrences);
             }
         }
-        if words.len() <= 10 {
-            println!("Cooccurrences look like {}", cooccurrences);
+        if cycles > 1 {
+            fs::remove_file(&cache_path)?;
         }
-
-        numpy::write_matrix(args.value_of("output").unwrap(), &cooccurrences)?;
     }
 
     Ok(())
 }
 
-/// Tokenize a string according to a dictionary. Unknowns will be 0.
-pub fn tokenize(content: &str, ids: &HashMap<&str, usize>) -> Vec<usize> {
-    /// Notice that we return indices (avoiding allocation)
-    content
-        // You can split on nonalphanumerics for a big speedup but the tokens are dubious.
-        //.split(|c: char| ! c.is_alphanumeric())
-        .split_word_bounds()
-        .map(|mention| *ids.get(mention).unwrap_or(&0))
-        // This will remove unknown words
-        // but this is troublesome because it makes the context windows too wide if you have few words
-        // because most words will be unknown
-        //.filter(|i| *i > 0)
-        .collect()
+/// Add the context vectors in the windows of a segment to the rows of their center words
+fn count_segment(mention_ids: &[usize], window: &Window, random_index: &RandomIndex,
+    contexts: &IndexVectors, cooccurrences: &mut ArrayViewMut2<f64>, skip_unknowns: bool) {
+    let height = cooccurrences.rows();
+    window.for_each_pair(mention_ids.len(), |mention_i, context_i, weight| {
+        if skip_unknowns && (mention_ids[mention_i] == 0 || mention_ids[context_i] == 0) {
+            return;
+        }
+        contexts.scaled_add_to(
+            &mut cooccurrences.row_mut(mention_ids[mention_i]), // row: center word
+            weight,
+            window.block(mention_i, context_i) * height
+                + mention_ids[context_i], // column: context word, in its block
+            random_index.rotation(context_i as isize - mention_i as isize));
+    });
+}
+
+/// Add up the context vectors of a segment's words into one for the segment, and add that, at
+/// unit length, to the row of every word in it
+fn reflect_segment(mention_ids: &[usize], contexts: &IndexVectors,
+    cooccurrences: &mut ArrayViewMut2<f64>, skip_unknowns: bool) {
+    let mut document = Array1::zeros(cooccurrences.cols());
+    for &id in mention_ids.iter().filter(|&&id| !skip_unknowns || id != 0) {
+        contexts.scaled_add_to(&mut document, 1.0, id, 0);
+    }
+    let norm = document.dot(&document).sqrt();
+    if norm == 0.0 {
+        return;
+    }
+    for &id in mention_ids.iter().filter(|&&id| !skip_unknowns || id != 0) {
+        cooccurrences.row_mut(id).scaled_add(1.0 / norm, &document);
+    }
 }
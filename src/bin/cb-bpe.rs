@@ -0,0 +1,61 @@
+//! Subword vocabulary: learn byte pair encoding merges
+//!
+//! This takes an input WET corpus piped to STDIN, counts its words (in bounded memory, like
+//! cb-vocab --memory) and learns byte pair encoding merges from the words seen at least
+//! --min-count times. It writes two files: the merges, and the list of subwords words can be
+//! split into, with how often the words seen are split into each (0 for characters that are only
+//! ever merged into longer subwords).
+//!
+//! Giving the cooccurrence counters the merges (--subwords) and the subword list (as the word
+//! list) makes them count subwords instead of words. Every word can be split into subwords, so
+//! only characters never seen here end up as the unknown word.
+//!
+
+// argument parsing
+#[macro_use] extern crate clap;
+// logging
+#[macro_use] extern crate log;
+extern crate env_logger;
+// lastly, this library
+extern crate cabarrus;
+
+use cabarrus::warc::WarcStreamer;
+use cabarrus::errors::*;
+use cabarrus::bpe::BpeLearner;
+use cabarrus::options;
+use cabarrus::tokens;
+use cabarrus::vocab::Vocab;
+
+pub fn main() {
+    // Main can't return a Result, and the ? operator needs the enclosing function to return Result
+    inner_main().expect("Could not recover. Exiting.");
+}
+pub fn inner_main() -> Result<()> {
+    env_logger::init().unwrap();
+    let args = app_from_crate!()
+        .args_from_usage(options::PHRASES)
+        .arg_from_usage("--num-merges=[N] 'learn at most N merges (default 30000)'")
+        .arg_from_usage("--min-count=[N] 'only learn from words seen at least N times (default 2)'")
+        .arg_from_usage("--memory=[MB] 'count words in about this many megabytes (default 1024)'")
+        .arg_from_usage("<merges> 'file in which to store the merges'")
+        .arg_from_usage("<subwords> 'file in which to store the subword list, to use as a wordlist'")
+        .get_matches();
+    let phrases = options::phrases(&args)?;
+    let limit = options::value(&args, "num-merges")?.unwrap_or(30000);
+    let min_count = options::value(&args, "min-count")?.unwrap_or(2);
+    let memory: usize = options::value(&args, "memory")?.unwrap_or(1024);
+
+    let mut learner = BpeLearner::with_memory(memory << 20);
+    for rec in WarcStreamer::new()? {
+        for word in tokens::phrase_words(&rec, &phrases) {
+            learner.add(&word);
+        }
+    }
+    info!("Counted {} words.", learner.total());
+
+    let (merges, subwords) = learner.learn(limit, min_count);
+    merges.write(args.value_of("merges").unwrap())?;
+    let (subwords, counts) = subwords.into_iter().unzip();
+    Vocab::new(subwords, Some(counts)).write(args.value_of("subwords").unwrap())?;
+    Ok(())
+}
@@ -1,12 +1,65 @@
 //! Example 0: Word cooccurrence counter
 //!
 //! This simple script takes an input WET corpus piped to STDIN and counts cooccurrences of input
-//! tokens, where tokens are defined by unicode, and cooccurrence is a window 21 words in
-//! diameter, and only words from a newline-separated list are considered.
+//! tokens, where tokens are defined by unicode, and cooccurrence is a window sliding over every
+//! token, by default reaching 10 tokens to either side (--radius), and only words from a
+//! newline-separated list are considered.
+//!
+//! The whitespace between tokens isn't a token, so it takes up no room in windows (see
+//! `cabarrus::tokens`).
 //!
 //! The output is a numpy file with a cooccurrence matrix. The rows are the center word, starting
 //! with the unknown word as word 0, and proceeding in the order they were specified in the input
-//! word list. The columns are the context words, and cooccurrence is always counted as 1 or 0.
+//! word list. The columns are the context words, and each time a context word is in a center
+//! word's window adds its weight: 1 by default, or decaying with distance (--weighting). The
+//! window can reach further to one side than the other (--left-radius, --right-radius), and a
+//! word is not its own context unless --include-self is given. By default windows run across
+//! whole documents, but they can be kept within paragraphs or sentences (--boundary).
+//!
+//! Contexts can also be told apart by side (--contexts=directional), so that "X precedes Y" and
+//! "X follows Y" are different columns, or by offset (--contexts=positional). Then there is a
+//! block of columns for each side or offset, each laid out like the rows, and the blocks are
+//! listed in a JSON file next to the output (output.npy.json).
+//!
+//! A dense matrix needs memory for every pair of words, which is impossible for large
+//! vocabularies. With --sparse, only the pairs that occur are kept, and the output is a sparse
+//! COO file instead (see `cabarrus::sparse` for the format). When even those don't fit, set a
+//! budget with --memory-limit: whenever it's reached, the pairs so far are written to disk as a
+//! sorted run (output.run0, output.run1...), and at the end the runs are merged into the output.
+//!
+//! When new input arrives, it can be counted into an existing dense output with --update, which
+//! adds to the matrix in place. The JSON file of the output has to show it was counted the same
+//! way and with the same word list, and each input has to be named with --input-name, which is
+//! recorded there so that the same one is never counted twice.
+//!
+//! For memory fixed up front however many pairs there are, --sketch=MB counts into a count-min
+//! sketch of that many megabytes instead (see `cabarrus::sketch`), with --sketch-depth rows of
+//! cells (4 by default). Its estimates can be too high, but never too low, and the bound on how
+//! far off they are is logged. Sketches from chunks of a corpus can be merged, queried for pairs
+//! and searched for a word's heaviest contexts with cb-sketch.
+//!
+//! Before any windows are formed, stopwords can be left out (--stopwords) and frequent words can
+//! be randomly discarded like in word2vec (--subsample, which needs a word list with counts, as
+//! made by cb-vocab). Both shrink the distances between the remaining words. Phrases learned by
+//! cb-phrases can be merged into single tokens first (--phrases).
+//!
+//! Words not in the word list are kept as the unknown word 0 by default. They can instead be
+//! dropped before windows are formed (--unknowns=drop), like word2vec, or skipped, taking up room
+//! in windows without being counted (--unknowns=skip). The rate of unknown words is logged and
+//! recorded in the JSON file next to the output.
+//!
+//! For an open vocabulary, words can instead be split into subwords (--subwords, with merges made
+//! by cb-bpe). Then the word list has to be the subword list cb-bpe wrote with them, and the rows
+//! and columns are subwords.
+//!
+//! Or, keeping whole words, each word can also pass its contexts on to its character n-grams
+//! (--char-ngrams=3-6 for lengths 3 to 6, counting the < and > marking the start and end of the
+//! word; see `cabarrus::ngrams`). The n-grams are hashed into --buckets rows (100000 by default)
+//! after the words' rows, and the contexts are still words. Unknown words have n-grams too, so
+//! rare words still count towards the n-grams they share with others, even with
+//! --unknowns=skip, which only leaves out their own row and column. cb-ngram-vectors then
+//! averages the rows of a word's n-grams, and its own row if it has one, into a vector for any
+//! word, fastText style. With many buckets, --sparse saves a lot of memory.
 //!
 
 // argument parsing
@@ -16,24 +69,24 @@
 extern crate env_logger;
 // numpy-like arrays
 extern crate ndarray;
-// better segmentation
-extern crate unicode_segmentation;
 // lastly, this library
 extern crate cabarrus;
 
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::cmp::min;
-use std::collections::HashMap;
+use std::borrow::Cow;
 use ndarray::prelude::*;
-use unicode_segmentation::UnicodeSegmentation;
 
 use cabarrus::warc::WarcStreamer;
 use cabarrus::errors::*;
+use cabarrus::meta::{Block, MatrixMeta};
+use cabarrus::ngrams::CharNgrams;
 use cabarrus::numpy;
+use cabarrus::options;
+use cabarrus::sketch::CountMinSketch;
+use cabarrus::sparse::{Accumulator, SparseMatrix, SpillingMatrix};
+use cabarrus::tokens::{self, Tokenizer, Unknowns};
+use cabarrus::vocab::Vocab;
+use cabarrus::window::Window;
 
-const WINDOW_RADIUS: usize = 10;
-const WINDOW_WIDTH: usize = 2 * WINDOW_RADIUS + 1;
 
 pub fn main() {
     // Main can't return a Result, and the ? operator needs the enclosing function to return Result
@@ -42,63 +95,160 @@ pub fn main() {
 pub fn inner_main() -> Result<()> {
     env_logger::init().unwrap();
     let args = app_from_crate!()
+        .args_from_usage(options::PHRASES)
+        .args_from_usage(options::TOKENIZER)
+        .args_from_usage(options::WINDOW)
+        .args_from_usage(options::UPDATE)
+        .arg_from_usage("--sparse 'count only the pairs that occur, and write them as a sparse COO matrix'")
+        .arg_from_usage("--memory-limit=[MB] 'like --sparse, but spill to disk and merge at the end beyond this many megabytes'")
+        .arg_from_usage("--sketch=[MB] 'count pairs approximately in a count-min sketch of this many megabytes'")
+        .arg_from_usage("--sketch-depth=[N] 'how many rows of cells the sketch has (default 4)'")
+        .arg_from_usage("--char-ngrams=[N-M] 'also count contexts for the character n-grams of each word, of these lengths (try 3-6)'")
+        .arg_from_usage("--buckets=[N] 'how many rows the character n-grams are hashed into (default 100000)'")
         .arg_from_usage("<wordlist> 'file containing words to look for, one per line'")
         .arg_from_usage("<output> 'file in which to store the resulting cooccurrence matrix'")
         .get_matches();
 
     // Read the word list from a file.
-    let mut words = vec![];
-    for line in BufReader::new(File::open(args.value_of("wordlist").unwrap())?).lines() {
-        words.push(line?);
-    }
-
-    // Note that word 0 is the unknown word.
-    let word_ids: HashMap<&str, usize> = words.iter()
-        .enumerate()
-        .map(|(id, word)| (word.as_ref(), id + 1))
-        .collect();
+    let mut tokenizer = options::tokenizer(&args, Vocab::read(args.value_of("wordlist").unwrap())?)?;
+    let vocab_size = tokenizer.vocab().len();
+    let window = options::window(&args)?;
 
     // This will be a table with rows of center words and columns of context words
     // It could be usize instead of f64 but this is easier for interop
     // and its range is still enough not to be a problem.
-    let mut cooccurrences: Array2<f64> = Array2::zeros((words.len() + 1, words.len() + 1));
+    // There are as many blocks of columns as the window tells contexts apart
+    let blocks = window.blocks();
+    let char_ngrams = match args.value_of("char-ngrams") {
+        Some(range) => Some(CharNgrams::from_range(range, options::value(&args, "buckets")?.unwrap_or(100000))?),
+        None => None
+    };
+    let buckets = char_ngrams.map(|char_ngrams| char_ngrams.buckets).unwrap_or(0);
+    let shape = (vocab_size + 1 + buckets, blocks.len() * (vocab_size + 1));
 
-    if words.len() < 25 {
-        info!("Collecting cooccurrences (with one another) of: {:?}", words);
+    if vocab_size < 25 {
+        info!("Collecting cooccurrences (with one another) of: {:?}", tokenizer.vocab().words());
     } else {
-        info!("Collecting cooccurrences (with one another) of {} words.", words.len());
+        info!("Collecting cooccurrences (with one another) of {} words.", vocab_size);
     }
-    
-    for rec in WarcStreamer::new()? {
-        let mention_ids = tokenize(&rec, &word_ids);
-        for mention_i in 0..mention_ids.len() {
-            for context_i in mention_i..min(mention_ids.len(), WINDOW_WIDTH) {
-                cooccurrences[[
-                    mention_ids[mention_i], // row: center word
-                    mention_ids[context_i] // column: context word
-                ]] += 1.0; // uniform window weight
-            }
+
+    let output = args.value_of("output").unwrap();
+    let mut meta = MatrixMeta::new("cb-cooccur", shape.0, shape.1);
+    meta.wordlist = args.value_of("wordlist").map(String::from);
+    meta.vocabulary = Some(tokenizer.vocab().fingerprint());
+    meta.tokenizer = Some(tokenizer.settings());
+    meta.row_blocks = Block::tile(&["center word".to_owned()], vocab_size + 1);
+    if let Some(char_ngrams) = char_ngrams {
+        info!("Counting contexts for character {}- to {}-grams, hashed into {} buckets.",
+            char_ngrams.min, char_ngrams.max, buckets);
+        meta.row_blocks.push(Block {
+            label: "character n-gram bucket".to_owned(), start: vocab_size + 1, width: buckets });
+        meta.char_ngrams = Some(char_ngrams);
+    }
+    meta.column_blocks = Block::tile(&blocks, vocab_size + 1);
+    meta.window = Some(window.clone());
+    if args.is_present("update")
+        && (args.is_present("sketch") || args.is_present("memory-limit") || args.is_present("sparse")) {
+        return Err(Error::Other("Only dense matrices can be added to. Sketches can be merged with \
+            cb-sketch merge instead.".to_owned()));
+    }
+    let existing = options::update(&args, output, &mut meta)?;
+
+    if existing.is_some() {
+        // Memory for nothing new: the counts go straight into the existing matrix
+        let outfile = numpy::open_matrix_mmap(output)?;
+        let mut cooccurrences = numpy::read_matrix_mmap(&outfile)?;
+        count(&mut tokenizer, &window, char_ngrams.as_ref(), &mut cooccurrences)?;
+    } else if let Some(megabytes) = options::value::<usize>(&args, "sketch")? {
+        // Memory for a fixed number of counts, which pairs share
+        let depth = options::value(&args, "sketch-depth")?.unwrap_or(4);
+        let seed = options::value(&args, "seed")?.unwrap_or(tokens::DEFAULT_SEED) as u64;
+        let mut cooccurrences = CountMinSketch::with_memory(shape.0, shape.1, megabytes << 20, depth, seed);
+        count(&mut tokenizer, &window, char_ngrams.as_ref(), &mut cooccurrences)?;
+        info!("Counted {} into {}x{} cells, so estimates are at most {:.2} too high with {:.2}% \
+            confidence.", cooccurrences.total(), depth, cooccurrences.width(),
+            cooccurrences.error_bound(), 100.0 * (1.0 - (-(depth as f64)).exp()));
+        cooccurrences.write(output)?;
+        meta.format = "cms".to_owned();
+    } else if let Some(megabytes) = options::value::<usize>(&args, "memory-limit")? {
+        // Memory for as many pairs as fit, spilling the rest to disk
+        let mut cooccurrences = SpillingMatrix::new(shape.0, shape.1, megabytes << 20, output);
+        count(&mut tokenizer, &window, char_ngrams.as_ref(), &mut cooccurrences)?;
+        let runs = cooccurrences.runs();
+        let nnz = cooccurrences.finish()?;
+        info!("Found {} distinct pairs, after {} spills.", nnz, runs);
+        meta.format = "coo".to_owned();
+    } else if args.is_present("sparse") {
+        // Memory for only the pairs that occur
+        let mut cooccurrences = SparseMatrix::new(shape.0, shape.1);
+        count(&mut tokenizer, &window, char_ngrams.as_ref(), &mut cooccurrences)?;
+        info!("Found {} distinct pairs.", cooccurrences.nnz());
+        cooccurrences.write_coo(output)?;
+        meta.format = "coo".to_owned();
+    } else {
+        // Memory for every pair
+        let mut cooccurrences: Array2<f64> = Array2::zeros(shape);
+        count(&mut tokenizer, &window, char_ngrams.as_ref(), &mut cooccurrences)?;
+        if vocab_size <= 10 {
+            println!("Cooccurrences look like {}", cooccurrences);
         }
+        numpy::write_matrix(output, &cooccurrences)?;
     }
-    if words.len() <= 10 {
-        println!("Cooccurrences look like {}", cooccurrences);
+    info!("{:.2}% of {} tokens were not in the word list ({:?}).",
+        100.0 * tokenizer.unknown_rate(), tokenizer.looked_up(), tokenizer.unknowns());
+    meta.tokens = Some(tokenizer.looked_up());
+    meta.unknown_rate = Some(tokenizer.unknown_rate());
+    if let Some(existing) = existing {
+        meta.absorb(existing);
     }
-    
-    numpy::write_matrix(args.value_of("output").unwrap(), &cooccurrences)?;
+    meta.write_for(output)?;
     Ok(())
 }
 
-/// Tokenize a string according to a dictionary. Unknowns will be 0.
-pub fn tokenize(content: &str, ids: &HashMap<&str, usize>) -> Vec<usize> {
-    /// Notice that we return indices (avoiding allocation)
-    content
-        // You can split on nonalphanumerics for a big speedup but the tokens are dubious.
-        //.split(|c: char| ! c.is_alphanumeric())
-        .split_word_bounds()
-        .map(|mention| *ids.get(mention).unwrap_or(&0))
-        // This will remove unknown words
-        // but this is troublesome because it makes the context windows too wide if you have few words
-        // because most words will be unknown
-        //.filter(|i| *i > 0)
-        .collect()
+/// Count the cooccurrences in the corpus on STDIN, and those of the words' character n-grams
+/// in the rows after the words', if wanted
+fn count<A: Accumulator>(tokenizer: &mut Tokenizer, window: &Window, char_ngrams: Option<&CharNgrams>,
+    cooccurrences: &mut A) -> Result<()> {
+    let width = tokenizer.vocab().len() + 1;
+    let skip_unknowns = tokenizer.unknowns() == Unknowns::Skip;
+    // The n-grams of the words in the word list are the same every time, so they're found once
+    let known_buckets: Vec<Vec<usize>> = match char_ngrams {
+        Some(char_ngrams) => Some(vec![]).into_iter()
+            .chain(tokenizer.vocab().words().iter().map(|word| char_ngrams.buckets(word)))
+            .collect(),
+        None => vec![]
+    };
+    for rec in WarcStreamer::new()? {
+        for segment in window.boundary.split(&rec) {
+            let (mention_ids, mention_buckets) = match char_ngrams {
+                Some(char_ngrams) => {
+                    let words = tokenizer.tokenize_words(segment);
+                    let ids: Vec<usize> = words.iter().map(|word| tokenizer.vocab().id(word)).collect();
+                    let buckets: Vec<Cow<[usize]>> = words.iter().zip(ids.iter()).map(|(word, &id)|
+                        if id == 0 { Cow::Owned(char_ngrams.buckets(word)) } else { Cow::Borrowed(&known_buckets[id][..]) }
+                    ).collect();
+                    (ids, buckets)
+                },
+                None => (tokenizer.tokenize(segment), vec![])
+            };
+            window.for_each_pair(mention_ids.len(), |mention_i, context_i, weight| {
+                if skip_unknowns && mention_ids[context_i] == 0 {
+                    return;
+                }
+                let column = window.block(mention_i, context_i) * width // column: which context block
+                    + mention_ids[context_i]; // and which context word in it
+                if !(skip_unknowns && mention_ids[mention_i] == 0) {
+                    cooccurrences.add(mention_ids[mention_i], column, weight); // row: center word
+                }
+                if let Some(buckets) = mention_buckets.get(mention_i) {
+                    // and rows: the center word's n-grams, which skipped unknown words still have
+                    for &bucket in buckets.iter() {
+                        cooccurrences.add(width + bucket, column, weight);
+                    }
+                }
+            });
+        }
+        cooccurrences.checkpoint()?;
+    }
+    Ok(())
 }
@@ -0,0 +1,201 @@
+//! Random indexing reconstruction experiment
+//!
+//! How well does random indexing keep the information in a cooccurrence matrix? This makes a
+//! random matrix C of `--vocab` words, accumulates each word's context vectors weighted by C the
+//! way cb-approx-cooccur does, and multiplies by the context vectors again to reconstruct C. Both
+//! are scaled to sum to 1, and the error is the sum of their absolute differences over
+//! vocab * vocab * fill.
+//!
+//! The sweep covers every combination of:
+//!
+//! - approach: how the context vectors are drawn: normal, uniform, or ternary-F for ternary
+//!   vectors with a fraction F nonzero (--approaches, default all of normal, uniform and ternary
+//!   with 0.05, 0.1, 0.2, 0.4 and 0.8)
+//! - norm: none, l1, l2 or qr (--norms, default all)
+//! - fill: the fraction of the entries of C that are 1 (--fills, default 0.001, 0.002, ..., 0.512)
+//! - factors: the rank of the context vectors (--factors, default 1, 2, 4, ..., 16384)
+//!
+//! Lists are comma separated, like --factors=64,256,1024. Each combination is tried --iterations
+//! times (default 10) with fresh vectors and a fresh C. C is binary unless --pareto gives the
+//! shape of a Pareto (Lomax, like numpy's) distribution to draw its entries from instead; the fill
+//! then only scales the error. Everything is drawn from --seed, so the same options give the same
+//! results.
+//!
+//! The output is a CSV file with the columns approach, norm, fill, factors, mean and stdev (of
+//! the error over the iterations), or a JSON array of the same with --json.
+//!
+//! This replaces exp-rand-idx.py and tasks/exp-rand-idx-task.py, which needed PBS and sqlite.
+//! The Python version used 10000 words, which takes a lot of memory at high ranks, so the default
+//! here is 1000.
+//!
+
+// argument parsing
+#[macro_use] extern crate clap;
+// logging
+#[macro_use] extern crate log;
+extern crate env_logger;
+// numpy-like arrays
+extern crate ndarray;
+// random numbers
+extern crate rand;
+// the output, as JSON
+#[macro_use] extern crate serde_derive;
+extern crate serde_json;
+// lastly, this library
+extern crate cabarrus;
+
+use clap::Arg;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use ndarray::prelude::*;
+use rand::{Rng, SeedableRng, StdRng};
+use rand::distributions::{IndependentSample, Range};
+
+use cabarrus::errors::*;
+use cabarrus::options;
+use cabarrus::randindex::{Distribution, Normalization, Permutation, RandomIndex};
+use cabarrus::tokens;
+
+/// The mean and standard deviation of the error for one combination of settings
+#[derive(Debug, Serialize)]
+struct Outcome {
+    approach: String,
+    norm: String,
+    fill: f64,
+    factors: usize,
+    mean: f64,
+    stdev: f64,
+}
+
+pub fn main() {
+    // Main can't return a Result, and the ? operator needs the enclosing function to return Result
+    inner_main().expect("Could not recover. Exiting.");
+}
+pub fn inner_main() -> Result<()> {
+    env_logger::init().unwrap();
+    let args = app_from_crate!()
+        .arg_from_usage("--vocab=[N] 'how many words the cooccurrence matrices have (default 1000)'")
+        .arg_from_usage("--iterations=[N] 'how many times to try each combination (default 10)'")
+        .arg(Arg::from_usage("--approaches=[NAME]... 'how to draw context vectors, comma separated: normal, uniform or ternary-F'")
+            .require_delimiter(true))
+        .arg(Arg::from_usage("--norms=[NAME]... 'how to normalize context vectors, comma separated: none, l1, l2 or qr'")
+            .require_delimiter(true))
+        .arg(Arg::from_usage("--fills=[F]... 'fractions of the cooccurrences that are 1, comma separated'")
+            .require_delimiter(true))
+        .arg(Arg::from_usage("--factors=[N]... 'ranks of the context vectors, comma separated'")
+            .require_delimiter(true))
+        .arg_from_usage("--pareto=[SHAPE] 'draw cooccurrences from a Pareto distribution of this shape (try 0.25) instead of 0 or 1'")
+        .arg_from_usage("--seed=[N] 'seed for every random choice, so runs are repeatable'")
+        .arg_from_usage("--json 'write a JSON array instead of CSV'")
+        .arg_from_usage("<output> 'file in which to store the results'")
+        .get_matches();
+
+    let vocab = options::value(&args, "vocab")?.unwrap_or(1000);
+    let iterations = options::value(&args, "iterations")?.unwrap_or(10);
+    let approaches: Vec<String> = options::values(&args, "approaches")?.unwrap_or_else(||
+        ["normal", "uniform", "ternary-0.05", "ternary-0.1", "ternary-0.2", "ternary-0.4",
+            "ternary-0.8"].iter().map(|&name| name.to_owned()).collect());
+    let norms: Vec<String> = options::values(&args, "norms")?.unwrap_or_else(||
+        ["none", "l1", "l2", "qr"].iter().map(|&name| name.to_owned()).collect());
+    let fills: Vec<f64> = options::values(&args, "fills")?.unwrap_or_else(||
+        (0..10).map(|i| 0.001 * (1 << i) as f64).collect());
+    let factors: Vec<usize> = options::values(&args, "factors")?.unwrap_or_else(||
+        (0..15).map(|i| 1 << i).collect());
+    let pareto: Option<f64> = options::value(&args, "pareto")?;
+    let mut rng = StdRng::from_seed(&[options::value(&args, "seed")?.unwrap_or(tokens::DEFAULT_SEED)]);
+
+    // Check every name before spending any time
+    let distributions = approaches.iter().map(|name| approach(name)).collect::<Result<Vec<_>>>()?;
+    let normalizations = norms.iter().map(|name| name.parse()).collect::<Result<Vec<Normalization>>>()?;
+    info!("Trying {} combinations {} times each, with {} words.",
+        approaches.len() * norms.len() * fills.len() * factors.len(), iterations, vocab);
+
+    let mut writer = BufWriter::new(File::create(args.value_of("output").unwrap())?);
+    let mut outcomes = vec![];
+    if !args.is_present("json") {
+        writeln!(writer, "approach,norm,fill,factors,mean,stdev")?;
+    }
+    for (name, &distribution) in approaches.iter().zip(distributions.iter()) {
+        for (norm, &normalization) in norms.iter().zip(normalizations.iter()) {
+            for &fill in fills.iter() {
+                for &rank in factors.iter() {
+                    let mut errors = vec![];
+                    for _ in 0..iterations {
+                        let index = RandomIndex {
+                            rank: rank,
+                            distribution: distribution,
+                            normalization: normalization,
+                            seed: rng.gen(),
+                            permutation: Permutation::None,
+                            hashed: false,
+                        };
+                        let contexts = index.vectors(vocab)?;
+                        let cooccurrences = random_cooccurrences(vocab, fill, pareto, &mut rng);
+                        errors.push(reconstruction_error(&cooccurrences, &contexts, fill));
+                    }
+                    let mean = errors.iter().sum::<f64>() / iterations as f64;
+                    let variance = errors.iter().map(|e| (e - mean) * (e - mean)).sum::<f64>()
+                        / iterations as f64;
+                    let outcome = Outcome {
+                        approach: name.clone(),
+                        norm: norm.clone(),
+                        fill: fill,
+                        factors: rank,
+                        mean: mean,
+                        stdev: variance.sqrt(),
+                    };
+                    info!("{:?}", outcome);
+                    if args.is_present("json") {
+                        outcomes.push(outcome);
+                    } else {
+                        // Written as they come, so a long sweep can be watched or cut short
+                        writeln!(writer, "{},{},{},{},{},{}", outcome.approach, outcome.norm,
+                            outcome.fill, outcome.factors, outcome.mean, outcome.stdev)?;
+                        writer.flush()?;
+                    }
+                }
+            }
+        }
+    }
+    if args.is_present("json") {
+        serde_json::to_writer_pretty(&mut writer, &outcomes)?;
+    }
+    Ok(())
+}
+
+/// Look up how to draw context vectors: normal, uniform or ternary-F
+fn approach(name: &str) -> Result<Distribution> {
+    if name.starts_with("ternary-") {
+        let fill = name["ternary-".len()..].parse().map_err(|e| Error::Other(
+            format!("Couldn't understand the fill of {:?}: {}", name, e)))?;
+        Distribution::from_name("ternary", fill)
+    } else {
+        Distribution::from_name(name, 0.0)
+    }
+}
+
+/// A square matrix of random cooccurrences: a fraction `fill` of them 1 and the rest 0, or all
+/// Pareto distributed with this shape
+fn random_cooccurrences<R: Rng>(vocab: usize, fill: f64, pareto: Option<f64>, rng: &mut R) -> Array2<f64> {
+    let uniform = Range::new(0.0, 1.0);
+    match pareto {
+        None => Array2::from_shape_fn((vocab, vocab),
+            |_| if uniform.ind_sample(rng) < fill { 1.0 } else { 0.0 }),
+        Some(shape) => Array2::from_shape_fn((vocab, vocab),
+            |_| (1.0 - uniform.ind_sample(rng)).powf(-1.0 / shape) - 1.0),
+    }
+}
+
+/// How far the matrix reconstructed from random projections is from the original, both scaled
+/// to sum to 1, as the sum of the absolute differences over vocab * vocab * fill
+fn reconstruction_error(cooccurrences: &Array2<f64>, contexts: &Array2<f64>, fill: f64) -> f64 {
+    // Each row adds up the context vectors of its word's contexts, as in cb-approx-cooccur
+    let projected = cooccurrences.t().dot(contexts);
+    let reconstructed = projected.dot(&contexts.t()).reversed_axes();
+    let total = cooccurrences.scalar_sum();
+    let reconstructed_total = reconstructed.scalar_sum();
+    let difference: f64 = cooccurrences.iter().zip(reconstructed.iter())
+        .map(|(&original, &estimate)| (original / total - estimate / reconstructed_total).abs())
+        .sum();
+    difference / (cooccurrences.len() as f64 * fill)
+}
@@ -0,0 +1,134 @@
+//! Sparse context vector speed experiment
+//!
+//! How much faster is cb-approx-cooccur when ternary context vectors are kept as only their
+//! nonzero entries? This draws a corpus of --tokens word ids from a Zipfian distribution over
+//! --vocab words, and adds up every word's context vectors in a --radius window the way
+//! cb-approx-cooccur does, once with the vectors dense and once sparse, from the same seed. Both
+//! are timed, and the largest difference between the two matrices is reported, which should be 0.
+//!
+//! The sweep covers every combination of:
+//!
+//! - fill: the fraction of the entries of the ternary vectors that are nonzero (--fills, default
+//!   0.01, 0.05 and 0.1)
+//! - factors: the rank of the context vectors (--factors, default 256, 1024 and 4096)
+//!
+//! Lists are comma separated, like --factors=64,256,1024. Everything is drawn from --seed, so the
+//! same options count the same corpus, though the times depend on the machine.
+//!
+//! The output is a CSV file with the columns fill, factors, tokens, dense (tokens per second),
+//! sparse (tokens per second), speedup and difference.
+//!
+
+// argument parsing
+#[macro_use] extern crate clap;
+// logging
+#[macro_use] extern crate log;
+extern crate env_logger;
+// numpy-like arrays
+extern crate ndarray;
+// random numbers
+extern crate rand;
+// lastly, this library
+extern crate cabarrus;
+
+use clap::Arg;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::Instant;
+use ndarray::prelude::*;
+use rand::{SeedableRng, StdRng};
+use rand::distributions::{IndependentSample, Range};
+
+use cabarrus::errors::*;
+use cabarrus::options;
+use cabarrus::randindex::{Distribution, IndexVectors, Normalization, Permutation, RandomIndex};
+use cabarrus::tokens;
+use cabarrus::window::Window;
+
+pub fn main() {
+    // Main can't return a Result, and the ? operator needs the enclosing function to return Result
+    inner_main().expect("Could not recover. Exiting.");
+}
+pub fn inner_main() -> Result<()> {
+    env_logger::init().unwrap();
+    let args = app_from_crate!()
+        .arg_from_usage("--vocab=[N] 'how many words the corpus has (default 5000)'")
+        .arg_from_usage("--tokens=[N] 'how many tokens the corpus has (default 1000000)'")
+        .arg_from_usage("--radius=[N] 'how many tokens on either side of a word are its context (default 5)'")
+        .arg(Arg::from_usage("--fills=[F]... 'fractions of the context vectors that are nonzero, comma separated'")
+            .require_delimiter(true))
+        .arg(Arg::from_usage("--factors=[N]... 'ranks of the context vectors, comma separated'")
+            .require_delimiter(true))
+        .arg_from_usage("--seed=[N] 'seed for the corpus and the context vectors, so runs are repeatable'")
+        .arg_from_usage("<output> 'file in which to store the results'")
+        .get_matches();
+
+    let vocab = options::value(&args, "vocab")?.unwrap_or(5000);
+    let length = options::value(&args, "tokens")?.unwrap_or(1000000);
+    let window = Window::symmetric(options::value(&args, "radius")?.unwrap_or(5));
+    let fills: Vec<f64> = options::values(&args, "fills")?.unwrap_or_else(|| vec![0.01, 0.05, 0.1]);
+    let factors: Vec<usize> = options::values(&args, "factors")?.unwrap_or_else(|| vec![256, 1024, 4096]);
+    let seed = options::value(&args, "seed")?.unwrap_or(tokens::DEFAULT_SEED);
+    let corpus = zipfian_corpus(vocab, length, seed);
+
+    let mut writer = BufWriter::new(File::create(args.value_of("output").unwrap())?);
+    writeln!(writer, "fill,factors,tokens,dense,sparse,speedup,difference")?;
+    for &fill in fills.iter() {
+        for &rank in factors.iter() {
+            let index = RandomIndex {
+                rank: rank,
+                distribution: Distribution::from_name("ternary", fill)?,
+                normalization: Normalization::None,
+                seed: seed,
+                permutation: Permutation::None,
+                hashed: false,
+            };
+            let dense_vectors = index.index_vectors(vocab + 1, true)?;
+            let (dense, dense_rate) = accumulate(&corpus, &window, &dense_vectors, rank);
+            let sparse_vectors = index.index_vectors(vocab + 1, false)?;
+            let (sparse, sparse_rate) = accumulate(&corpus, &window, &sparse_vectors, rank);
+            let difference = dense.iter().zip(sparse.iter())
+                .fold(0.0f64, |largest, (d, s)| largest.max((d - s).abs()));
+            info!("Fill {}, rank {}: {:.0} tokens/s dense, {:.0} sparse, {:.1} times faster, \
+                difference {}.", fill, rank, dense_rate, sparse_rate, sparse_rate / dense_rate,
+                difference);
+            // Written as they come, so a long sweep can be watched or cut short
+            writeln!(writer, "{},{},{},{},{},{},{}", fill, rank, length, dense_rate, sparse_rate,
+                sparse_rate / dense_rate, difference)?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Word ids from 1 to `vocab`, the nth most frequent drawn with a chance proportional to 1/n
+fn zipfian_corpus(vocab: usize, length: usize, seed: usize) -> Vec<usize> {
+    let mut cumulative = Vec::with_capacity(vocab);
+    let mut total = 0.0;
+    for rank in 1..vocab + 1 {
+        total += 1.0 / rank as f64;
+        cumulative.push(total);
+    }
+    let mut rng = StdRng::from_seed(&[seed]);
+    let uniform = Range::new(0.0, total);
+    (0..length).map(|_| {
+        let x = uniform.ind_sample(&mut rng);
+        1 + match cumulative.binary_search_by(|c| c.partial_cmp(&x).unwrap()) {
+            Ok(i) | Err(i) => ::std::cmp::min(i, vocab - 1)
+        }
+    }).collect()
+}
+
+/// Add up the context vectors of every word's contexts into rows of `rank` columns, as
+/// cb-approx-cooccur does, returning the rows and how many tokens were counted per second
+fn accumulate(corpus: &[usize], window: &Window, contexts: &IndexVectors, rank: usize)
+    -> (Array2<f64>, f64) {
+    let mut rows = Array2::zeros((contexts.rows(), rank));
+    let start = Instant::now();
+    window.for_each_pair(corpus.len(), |mention_i, context_i, weight| {
+        contexts.scaled_add_to(&mut rows.row_mut(corpus[mention_i]), weight, corpus[context_i], 0);
+    });
+    let elapsed = start.elapsed();
+    let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+    (rows, corpus.len() as f64 / seconds)
+}
@@ -0,0 +1,177 @@
+//! Merge cooccurrence matrices counted with different word lists
+//!
+//! cb-sum-matrices needs every matrix to have the same rows and columns in the same order. This
+//! instead takes each matrix with the word list it was counted with, makes a word list of every
+//! word in any of them (or, with --intersection, only the words in all of them), and adds up the
+//! counts of the same words. Words left out of the merged list are counted as its unknown word 0,
+//! just as they would be if they had been counted with it.
+//!
+//! Columns are remapped like the rows. If the columns are in blocks (from --contexts in
+//! cb-cooccur), every block is remapped the same way. Matrices whose columns aren't words, like
+//! the random projections of cb-approx-cooccur --open, need --rows-only, and then their columns
+//! have to match already. Only hashed context vectors (--open) are the same whatever the word
+//! list, though. Without --open, the context vectors are drawn by word id, so each word list gives
+//! words different ones, and those matrices are refused.
+//!
+//! Each matrix needs its JSON file, which has to show it was counted the same way as the others:
+//! with the same window, tokenizing, character n-grams and context vectors. Only raw counts add
+//! up, so weighted matrices (like those from cb-transform) are refused too.
+//!
+//! The matrices can be dense or sparse, and are streamed through rather than read into memory.
+//! The output is dense unless --sparse is given, which needs memory for its entries.
+//!
+//! cb-merge-matrices merged.npy merged.wordlist a.npy a.wordlist b.coo b.wordlist
+//!
+
+// argument parsing
+#[macro_use] extern crate clap;
+// logging
+#[macro_use] extern crate log;
+extern crate env_logger;
+// lastly, this library
+extern crate cabarrus;
+
+use cabarrus::errors::*;
+use cabarrus::meta::{Block, MatrixMeta};
+use cabarrus::numpy;
+use cabarrus::sparse::{self, Accumulator, SparseMatrix};
+use cabarrus::vocab::Vocab;
+
+pub fn main() {
+    // Main can't return a Result, and the ? operator needs the enclosing function to return Result
+    inner_main().expect("Could not recover. Exiting.");
+}
+pub fn inner_main() -> Result<()> {
+    env_logger::init().unwrap();
+    let args = app_from_crate!()
+        .arg_from_usage("--intersection 'keep only the words in every word list, instead of those in any'")
+        .arg_from_usage("--rows-only 'only the rows are words; the columns must already match'")
+        .arg_from_usage("--sparse 'write a sparse COO matrix instead of a dense one'")
+        .arg_from_usage("<output> 'file in which to store the merged matrix'")
+        .arg_from_usage("<output-wordlist> 'file in which to store the merged word list'")
+        .arg_from_usage("<inputs>... 'matrices to merge, each followed by its word list'")
+        .get_matches();
+    let output = args.value_of("output").unwrap();
+    let inputs: Vec<&str> = args.values_of("inputs").unwrap().collect();
+    if inputs.len() % 2 != 0 {
+        return Err(Error::Other(
+            "Every matrix to merge needs to be followed by its word list.".to_owned()));
+    }
+    let rows_only = args.is_present("rows-only");
+
+    // Line up the word lists
+    let mut pairs = vec![];
+    for pair in inputs.chunks(2) {
+        pairs.push((pair[0], Vocab::read(pair[1])?));
+    }
+    // Only raw counts made the same way can be added up
+    let metas = pairs.iter().map(|pair| MatrixMeta::read_for(pair.0)).collect::<Result<Vec<_>>>()?;
+    for (&(path, _), meta) in pairs.iter().zip(metas.iter()) {
+        let refuse = |why: String| Err(Error::Other(format!("Can't merge {}: {}", path, why)));
+        if let Some(ref weighting) = meta.weighting {
+            return refuse(format!("it's weighted ({}), and only raw counts can be added up.",
+                weighting));
+        }
+        if let Some(ref random_index) = meta.random_index {
+            if !random_index.hashed {
+                return refuse("its context vectors were drawn by word id, so they differ from one \
+                    word list to another. Count with cb-approx-cooccur --open instead.".to_owned());
+            }
+            if !rows_only {
+                return refuse("its columns are random projections, not words, so it needs \
+                    --rows-only.".to_owned());
+            }
+        }
+        let first = &metas[0];
+        if meta.window != first.window {
+            return refuse(format!("it was counted with a different window than {}: {:?}.",
+                pairs[0].0, meta.window));
+        }
+        if meta.tokenizer != first.tokenizer {
+            return refuse(format!("its words were tokenized differently than {}'s: {:?}.",
+                pairs[0].0, meta.tokenizer));
+        }
+        if meta.char_ngrams != first.char_ngrams {
+            return refuse(format!("it has rows for different character n-grams than {}: {:?}.",
+                pairs[0].0, meta.char_ngrams));
+        }
+        if meta.random_index != first.random_index {
+            return refuse(format!("its random context vectors were made differently than {}'s: {:?}.",
+                pairs[0].0, meta.random_index));
+        }
+    }
+    let vocabs: Vec<&Vocab> = pairs.iter().map(|pair| &pair.1).collect();
+    let merged = Vocab::combine(&vocabs, args.is_present("intersection"));
+    let width = merged.len() + 1;
+    info!("Merging {} matrices into {} words.", pairs.len(), merged.len());
+
+    // Every matrix needs the same shape, apart from the word lists
+    let mut columns = None;
+    let mut layouts = vec![];
+    for &(path, ref vocab) in pairs.iter() {
+        let (rows, cols) = sparse::shape(path)?;
+        if rows != vocab.len() + 1 {
+            return Err(Error::InvalidDimensions(format!(
+                "{} has {} rows, but its word list has {} words (and the unknown word).",
+                path, rows, vocab.len())));
+        }
+        // Columns are words in blocks, unless they aren't words at all
+        let layout = if rows_only { cols } else if cols % rows == 0 { cols / rows } else {
+            return Err(Error::InvalidDimensions(format!(
+                "{} has {} columns, which isn't a whole number of blocks of {} words. \
+                Maybe its columns aren't words (--rows-only)?", path, cols, rows)));
+        };
+        if columns.is_some() && columns != Some(layout) {
+            return Err(Error::InvalidDimensions(format!(
+                "{} has {} {}, unlike the matrices before it.",
+                path, layout, if rows_only { "columns" } else { "blocks of columns" })));
+        }
+        columns = Some(layout);
+        layouts.push(vocab.ids_in(&merged));
+    }
+    let columns = columns.unwrap_or(1);
+    let shape = (width, if rows_only { columns } else { columns * width });
+
+    // Add up every matrix, remapped
+    let add_all = |accum: &mut Accumulator| -> Result<()> {
+        for (&(path, ref vocab), ids) in pairs.iter().zip(layouts.iter()) {
+            info!("Adding {}", path);
+            let rows = vocab.len() + 1;
+            sparse::for_each_entry(path, |row, col, value| {
+                let col = if rows_only { col } else { col / rows * width + ids[col % rows] };
+                accum.add(ids[row], col, value);
+            })?;
+        }
+        Ok(())
+    };
+    let mut meta = MatrixMeta::new("cb-merge-matrices", shape.0, shape.1);
+    if !rows_only {
+        meta.column_blocks = Block::tile(&vec!["word".to_owned(); columns], width);
+    }
+    if args.is_present("sparse") {
+        let mut accum = SparseMatrix::new(shape.0, shape.1);
+        add_all(&mut accum)?;
+        accum.write_coo(output)?;
+        meta.format = "coo".to_owned();
+    } else {
+        let accumfile = numpy::create_empty_mmap(output, &[shape.0, shape.1])?;
+        let mut accum = numpy::read_matrix_mmap(&accumfile)?;
+        add_all(&mut accum)?;
+    }
+    merged.write(args.value_of("output-wordlist").unwrap())?;
+
+    // Describe the result, which was counted like every input
+    let first = metas.into_iter().next().unwrap();
+    meta.window = first.window;
+    meta.tokenizer = first.tokenizer;
+    meta.random_index = first.random_index;
+    if !rows_only && first.column_blocks.len() == columns {
+        let labels: Vec<String> = first.column_blocks.into_iter().map(|b| b.label).collect();
+        meta.column_blocks = Block::tile(&labels, width);
+    }
+    meta.wordlist = args.value_of("output-wordlist").map(String::from);
+    meta.vocabulary = Some(merged.fingerprint());
+    meta.row_blocks = Block::tile(&["word".to_owned()], width);
+    meta.write_for(output)?;
+    Ok(())
+}
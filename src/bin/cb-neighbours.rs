@@ -0,0 +1,102 @@
+//! Find the likely neighbours of words, from a matrix made by cb-approx-cooccur
+//!
+//! When cb-approx-cooccur rotates context vectors by where they are (--permutation), each row
+//! remembers which words came before and after the word, not just which were nearby. This
+//! rotates a word's row back the way contexts at some distance were rotated and compares it with
+//! every word's context vector, so the best matches are the words most likely seen there.
+//!
+//! Neighbours are found to the right (after the word) unless --left is given, --distance tokens
+//! away (1 by default, up to the radius the matrix was counted with). The settings the matrix was
+//! made with are read from its JSON file, including whether its context vectors were hashed
+//! (--open), so the same context vectors are made again. That rules out reflective --cycles,
+//! whose contexts were other rows. For each word, the best --top matches are printed as tab
+//! separated lines: the word, the neighbour and their cosine similarity.
+//!
+//! Without a permutation, left and right neighbours come out the same, and are just the words
+//! that cooccur most.
+//!
+
+// argument parsing
+#[macro_use] extern crate clap;
+// logging
+#[macro_use] extern crate log;
+extern crate env_logger;
+// lastly, this library
+extern crate cabarrus;
+
+use cabarrus::errors::*;
+use cabarrus::meta::MatrixMeta;
+use cabarrus::numpy;
+use cabarrus::options;
+use cabarrus::randindex::{IndexVectors, Permutation};
+use cabarrus::vocab::Vocab;
+
+pub fn main() {
+    // Main can't return a Result, and the ? operator needs the enclosing function to return Result
+    inner_main().expect("Could not recover. Exiting.");
+}
+pub fn inner_main() -> Result<()> {
+    env_logger::init().unwrap();
+    let args = app_from_crate!()
+        .arg_from_usage("--left 'find the words before each word, rather than after'")
+        .arg_from_usage("--distance=[N] 'how many tokens away the neighbours are (default 1)'")
+        .arg_from_usage("--top=[N] 'how many neighbours to find for each word (default 10)'")
+        .arg_from_usage("<matrix> 'cooccurrence matrix from cb-approx-cooccur, with its JSON file'")
+        .arg_from_usage("<words>... 'words to find the neighbours of'")
+        .get_matches();
+    let path = args.value_of("matrix").unwrap();
+    let distance: usize = options::value(&args, "distance")?.unwrap_or(1);
+    let top = options::value(&args, "top")?.unwrap_or(10);
+
+    // Everything needed to make the context vectors again
+    let meta = MatrixMeta::read_for(path)?;
+    let (random_index, window, wordlist) = match (meta.random_index, meta.window, meta.wordlist) {
+        (Some(random_index), Some(window), Some(wordlist)) => (random_index, window, wordlist),
+        _ => return Err(Error::Other(format!(
+            "{} doesn't say how its context vectors were made. Is the matrix from cb-approx-cooccur?",
+            MatrixMeta::path_for(path).display())))
+    };
+    if meta.cycles.is_some() {
+        return Err(Error::Other("The matrix was made by reflective cycles (--cycles), whose context \
+            vectors were other rows of the matrix, not random ones that can be made again.".to_owned()));
+    }
+    if random_index.permutation == Permutation::None {
+        warn!("The context vectors weren't permuted, so every neighbour looks the same.");
+    }
+    let radius = if args.is_present("left") { window.left } else { window.right };
+    if distance < 1 || distance > radius {
+        return Err(Error::Other(format!(
+            "The distance should be from 1 to the radius of the window ({}), not {}.", radius, distance)));
+    }
+    let vocab = Vocab::read(&wordlist)?;
+    let vocab_size = vocab.len();
+
+    // The contexts that far away were in one block, and the unknown word isn't worth finding
+    let (offset, block) = if args.is_present("left") {
+        (-(distance as isize), window.block(distance, 0))
+    } else {
+        (distance as isize, window.block(0, distance))
+    };
+    let (contexts, first) = if random_index.hashed {
+        (IndexVectors::Sparse(random_index.hashed_vectors(vocab.words(), block)?), 0)
+    } else {
+        let blocks = window.blocks().len();
+        (random_index.index_vectors(blocks * (vocab_size + 1), false)?, block * (vocab_size + 1) + 1)
+    };
+
+    let matfile = numpy::open_matrix_mmap(path)?;
+    let matrix = numpy::read_matrix_mmap(&matfile)?;
+    for word in args.values_of("words").unwrap() {
+        let id = vocab.id(word);
+        if id == 0 {
+            warn!("{:?} isn't in the word list, so it has no row.", word);
+            continue;
+        }
+        let neighbours = random_index.neighbours(matrix.row(id), &contexts, first, vocab_size,
+            offset, top);
+        for (i, score) in neighbours {
+            println!("{}\t{}\t{}", word, vocab.word(i + 1).unwrap_or(""), score);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,118 @@
+//! Make vectors for any words, even ones never seen, from the character n-grams of cb-cooccur
+//!
+//! cb-cooccur --char-ngrams counts contexts for the character n-grams of words, hashed into rows
+//! after the words' own rows (see `cabarrus::ngrams`). A word's vector is then the average of
+//! the rows of its n-grams and its own row, if it is in the word list the matrix was counted
+//! with, like fastText. So words that are rare or missing from the word list get vectors from
+//! the words they share prefixes, suffixes and stems with.
+//!
+//! The words are read from a word list (one per line, as made by cb-vocab or by hand), and the
+//! output is a numpy matrix with a row for each, after the unknown word as row 0, which is left
+//! at 0. Its columns are the contexts of the input matrix, which can be dense or sparse (COO).
+//! The JSON file written next to it names the word list, so it can be used like any matrix from
+//! cb-cooccur, say with cb-transform and cb-metrics.
+//!
+//! The word list the matrix was counted with is found where its JSON file says, which is the path
+//! as it was given to cb-cooccur, so from another directory it has to be given with --wordlist.
+//! Count-min sketches (cb-cooccur --sketch) are refused, since they can't list their entries.
+//!
+
+// argument parsing
+#[macro_use] extern crate clap;
+// logging
+#[macro_use] extern crate log;
+extern crate env_logger;
+// numpy-like arrays
+extern crate ndarray;
+// lastly, this library
+extern crate cabarrus;
+
+use ndarray::prelude::*;
+use cabarrus::errors::*;
+use cabarrus::meta::{Block, MatrixMeta};
+use cabarrus::numpy;
+use cabarrus::sparse;
+use cabarrus::vocab::Vocab;
+
+pub fn main() {
+    // Main can't return a Result, and the ? operator needs the enclosing function to return Result
+    inner_main().expect("Could not recover. Exiting.");
+}
+pub fn inner_main() -> Result<()> {
+    env_logger::init().unwrap();
+    let args = app_from_crate!()
+        .arg_from_usage("--wordlist=[FILE] 'word list the matrix was counted with, if not where its JSON file says'")
+        .arg_from_usage("<matrix> 'cooccurrence matrix from cb-cooccur --char-ngrams, with its JSON file'")
+        .arg_from_usage("<words> 'file containing the words to make vectors for, one per line'")
+        .arg_from_usage("<output> 'file in which to store the vectors'")
+        .get_matches();
+    let path = args.value_of("matrix").unwrap();
+    let output = args.value_of("output").unwrap();
+
+    // The word list and n-grams the matrix was counted with
+    let meta = MatrixMeta::read_for(path)?;
+    if meta.format == "cms" {
+        return Err(Error::Other(format!("{} is a count-min sketch, which can't list the counts of \
+            the n-grams' rows. Count the matrix without --sketch.", path)));
+    }
+    let wordlist = args.value_of("wordlist").map(String::from).or_else(|| meta.wordlist.clone());
+    let (char_ngrams, wordlist) = match (meta.char_ngrams, wordlist) {
+        (Some(char_ngrams), Some(wordlist)) => (char_ngrams, wordlist),
+        _ => return Err(Error::Other(format!(
+            "{} doesn't say which character n-grams were counted. Is the matrix from cb-cooccur \
+            --char-ngrams?", MatrixMeta::path_for(path).display())))
+    };
+    let vocab = Vocab::read(&wordlist)?;
+    if meta.vocabulary.is_some() && meta.vocabulary != Some(vocab.fingerprint()) {
+        warn!("{} has changed since the matrix was counted, so the words' own rows will be wrong.",
+            wordlist);
+    }
+    let width = vocab.len() + 1;
+    let (rows, cols) = sparse::shape(path)?;
+    if rows != width + char_ngrams.buckets {
+        return Err(Error::InvalidDimensions(format!(
+            "{} should have {} rows, for {} words and {} n-gram buckets, but it has {}.",
+            path, width + char_ngrams.buckets, vocab.len(), char_ngrams.buckets, rows)));
+    }
+    let words = Vocab::read(args.value_of("words").unwrap())?;
+
+    // Which row of the output each row of the matrix is part of, and how much it counts there
+    let mut parts: Vec<Vec<(usize, f64)>> = vec![vec![]; rows];
+    let mut known = 0;
+    for (i, word) in words.words().iter().enumerate() {
+        let mut sources: Vec<usize> = char_ngrams.buckets(word).into_iter()
+            .map(|bucket| width + bucket)
+            .collect();
+        let id = vocab.id(word);
+        if id != 0 {
+            sources.push(id);
+            known += 1;
+        }
+        if sources.is_empty() {
+            warn!("{:?} is too short to have any n-grams, so its vector is 0.", word);
+            continue;
+        }
+        let share = 1.0 / sources.len() as f64;
+        for source in sources {
+            parts[source].push((i + 1, share));
+        }
+    }
+    info!("Making vectors for {} words, {} of them in the word list.", words.len(), known);
+
+    let mut vectors: Array2<f64> = Array2::zeros((words.len() + 1, cols));
+    sparse::for_each_entry(path, |row, col, value| {
+        for &(target, share) in parts[row].iter() {
+            vectors[[target, col]] += share * value;
+        }
+    })?;
+    numpy::write_matrix(output, &vectors)?;
+
+    let mut vectors_meta = MatrixMeta::new("cb-ngram-vectors", vectors.rows(), cols);
+    vectors_meta.wordlist = args.value_of("words").map(String::from);
+    vectors_meta.vocabulary = Some(words.fingerprint());
+    vectors_meta.row_blocks = Block::tile(&["word from its n-grams".to_owned()], words.len() + 1);
+    vectors_meta.column_blocks = meta.column_blocks;
+    vectors_meta.window = meta.window;
+    vectors_meta.write_for(output)?;
+    Ok(())
+}
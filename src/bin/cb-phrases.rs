@@ -0,0 +1,63 @@
+//! Phrase detection: learn which word pairs should be merged into one token
+//!
+//! This takes an input WET corpus piped to STDIN, counts every word and every pair of adjacent
+//! words, and writes a phrase table of the pairs that score above a threshold, like "new york"
+//! or "machine learning". Counting happens in bounded memory (--memory), so for a very large
+//! corpus the counts of rare pairs are approximate; --min-count should be well above that.
+//!
+//! Other binaries merge the phrases into single tokens ("new_york") with --phrases. Giving this
+//! binary the tables of earlier passes the same way makes it learn longer phrases, so:
+//!
+//! cb-phrases pass1.phrases < corpus
+//! cb-phrases --phrases=pass1.phrases pass2.phrases < corpus
+//! cb-vocab --phrases=pass1.phrases --phrases=pass2.phrases wordlist < corpus
+//!
+
+// argument parsing
+#[macro_use] extern crate clap;
+// logging
+#[macro_use] extern crate log;
+extern crate env_logger;
+// lastly, this library
+extern crate cabarrus;
+
+use cabarrus::warc::WarcStreamer;
+use cabarrus::errors::*;
+use cabarrus::options;
+use cabarrus::phrases::{PhraseLearner, Scoring};
+use cabarrus::tokens;
+
+pub fn main() {
+    // Main can't return a Result, and the ? operator needs the enclosing function to return Result
+    inner_main().expect("Could not recover. Exiting.");
+}
+pub fn inner_main() -> Result<()> {
+    env_logger::init().unwrap();
+    let args = app_from_crate!()
+        .args_from_usage(options::PHRASES)
+        .arg_from_usage("--scoring=[METHOD] 'how to score pairs: word2vec (default), pmi or npmi'")
+        .arg_from_usage("--threshold=[SCORE] 'lowest score for a phrase (default 100 for word2vec, 5 for pmi, 0.5 for npmi)'")
+        .arg_from_usage("--min-count=[N] 'ignore pairs seen fewer than N times (default 5)'")
+        .arg_from_usage("--memory=[MB] 'count in about this many megabytes (default 1024)'")
+        .arg_from_usage("<output> 'file in which to store the phrase table'")
+        .get_matches();
+    let phrases = options::phrases(&args)?;
+    let scoring = options::value(&args, "scoring")?.unwrap_or(Scoring::Word2vec);
+    let threshold = options::value(&args, "threshold")?.unwrap_or(match scoring {
+        Scoring::Word2vec => 100.0,
+        Scoring::Pmi => 5.0,
+        Scoring::Npmi => 0.5,
+    });
+    let min_count = options::value(&args, "min-count")?.unwrap_or(5);
+    let memory: usize = options::value(&args, "memory")?.unwrap_or(1024);
+
+    let mut learner = PhraseLearner::with_memory(memory << 20);
+    for rec in WarcStreamer::new()? {
+        learner.add(&tokens::phrase_words(&rec, &phrases).collect::<Vec<_>>());
+    }
+
+    let table = learner.learn(scoring, min_count, threshold);
+    info!("Found {} phrases in {} tokens.", table.len(), learner.total());
+    table.write(args.value_of("output").unwrap())?;
+    Ok(())
+}
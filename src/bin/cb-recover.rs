@@ -0,0 +1,135 @@
+//! Recover approximate cooccurrence counts from random indexing
+//!
+//! cb-approx-cooccur adds up the random context vectors of each word's contexts, and with
+//! --context it writes those vectors out. Projecting a word's row back onto a context's vector
+//! estimates how often they were seen together (see `cabarrus::randindex`), along with the
+//! standard deviation of the noise from all the other contexts, which shrinks with the rank.
+//!
+//! By default, word pairs are read from STDIN, one per line as a word and a context separated by
+//! a tab, and each is printed back with the estimate and its error added as two more columns.
+//! With --reconstruct=FILE, the cooccurrences of the first --words words of the word list (all of
+//! them by default, or the most frequent N with a word list from cb-vocab) with one another are
+//! written to FILE as a numpy matrix, with the unknown word first as usual, and their errors to
+//! FILE.error.
+//!
+//! Both matrices need their JSON files, to tell which word list and settings they were made with.
+//! Directional or positional contexts come in blocks, and the block of the contexts wanted is
+//! given by --block (see the JSON file of the contexts). Permuted context vectors were rotated by
+//! how far each context was from its word, so then the distance has to be given as --offset, and
+//! the estimates are only of contexts that far away (negative offsets are to the left). Matrices
+//! made by reflective cycles (--cycles) are refused, since their contexts weren't random vectors,
+//! and so are contexts whose JSON file shows other settings (like --rank or --seed) than the
+//! matrix's.
+//!
+
+// argument parsing
+#[macro_use] extern crate clap;
+// logging
+#[macro_use] extern crate log;
+extern crate env_logger;
+// numpy-like arrays
+#[macro_use] extern crate ndarray;
+// lastly, this library
+extern crate cabarrus;
+
+use std::io::{self, BufRead};
+use cabarrus::errors::*;
+use cabarrus::meta::{Block, MatrixMeta};
+use cabarrus::numpy;
+use cabarrus::options;
+use cabarrus::randindex::Permutation;
+use cabarrus::vocab::Vocab;
+
+pub fn main() {
+    // Main can't return a Result, and the ? operator needs the enclosing function to return Result
+    inner_main().expect("Could not recover. Exiting.");
+}
+pub fn inner_main() -> Result<()> {
+    env_logger::init().unwrap();
+    let args = app_from_crate!()
+        .arg_from_usage("--block=[N] 'which block of context vectors the contexts are in (default 0)'")
+        .arg_from_usage("--offset=[N] 'how far the contexts were from their words, for permuted context vectors'")
+        .arg_from_usage("--reconstruct=[FILE] 'write the cooccurrences of the first --words words to FILE instead of reading pairs'")
+        .arg_from_usage("--words=[N] 'how many words to reconstruct the cooccurrences of (default all)'")
+        .arg_from_usage("<matrix> 'matrix of accumulated context vectors from cb-approx-cooccur'")
+        .arg_from_usage("<contexts> 'the context vectors it was made with, from cb-approx-cooccur --context'")
+        .get_matches();
+    let matrix_path = args.value_of("matrix").unwrap();
+    let contexts_path = args.value_of("contexts").unwrap();
+
+    // Everything about how the matrix was made is in its JSON file
+    let meta = MatrixMeta::read_for(matrix_path)?;
+    let (random_index, wordlist) = match (meta.random_index.clone(), meta.wordlist.clone()) {
+        (Some(random_index), Some(wordlist)) => (random_index, wordlist),
+        _ => return Err(Error::Other(format!(
+            "{} doesn't say how its context vectors were made. Is the matrix from cb-approx-cooccur?",
+            MatrixMeta::path_for(matrix_path).display())))
+    };
+    if meta.cycles.is_some() {
+        return Err(Error::Other("The matrix was made by reflective cycles (--cycles), whose context \
+            vectors were other rows of the matrix, not random ones that can be made again.".to_owned()));
+    }
+    if random_index.hashed {
+        return Err(Error::Other("The matrix was made with hashed context vectors (--open), which \
+            can't be written out with --context.".to_owned()));
+    }
+    let offset = match (random_index.permutation, options::value(&args, "offset")?) {
+        (Permutation::None, _) => 0,
+        (_, Some(offset)) => offset,
+        (_, None) => return Err(Error::Other(
+            "The context vectors were permuted, so say how far the contexts were with --offset."
+            .to_owned()))
+    };
+    let contexts_meta = MatrixMeta::read_for(contexts_path)?;
+    if contexts_meta.random_index.as_ref() != Some(&random_index) {
+        return Err(Error::Other(format!(
+            "{} wasn't made with the same context vectors as {}, so the estimates would be \
+            nonsense: {:?} rather than {:?}.", contexts_path, matrix_path,
+            contexts_meta.random_index, random_index)));
+    }
+    let vocab = Vocab::read(&wordlist)?;
+    let width = vocab.len() + 1;
+
+    let matfile = numpy::open_matrix_mmap(matrix_path)?;
+    let matrix = numpy::read_matrix_mmap(&matfile)?;
+    let contexts_file = numpy::open_matrix_mmap(contexts_path)?;
+    let contexts = numpy::read_matrix_mmap(&contexts_file)?;
+    let block: usize = options::value(&args, "block")?.unwrap_or(0);
+    if contexts.cols() != matrix.cols() || (block + 1) * width > contexts.rows() {
+        return Err(Error::Other(format!(
+            "There's no block {} of {} context vectors of rank {} in the {}x{} contexts.",
+            block, width, matrix.cols(), contexts.rows(), contexts.cols())));
+    }
+    let first = block * width;
+
+    if let Some(output) = args.value_of("reconstruct") {
+        let words = ::std::cmp::min(options::value(&args, "words")?.unwrap_or(vocab.len()), vocab.len());
+        info!("Reconstructing the cooccurrences of {} words.", words);
+        let (estimates, errors) = random_index.recover(matrix.slice(s![..words + 1, ..]),
+            contexts.slice(s![first..first + words + 1, ..]), offset, contexts.rows());
+        numpy::write_matrix(output, &estimates)?;
+        numpy::write_matrix(format!("{}.error", output), &errors)?;
+        let mut meta = MatrixMeta::new("cb-recover", words + 1, words + 1);
+        meta.wordlist = Some(wordlist);
+        meta.row_blocks = Block::tile(&["center word".to_owned()], words + 1);
+        meta.column_blocks = Block::tile(&["context word".to_owned()], words + 1);
+        meta.window = contexts_meta.window;
+        meta.random_index = Some(random_index);
+        meta.write_for(output)?;
+    } else {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let line = line?;
+            let pair: Vec<&str> = line.split('\t').collect();
+            if pair.len() != 2 {
+                warn!("Skipping {:?}, which isn't a word and a context separated by a tab.", line);
+                continue;
+            }
+            let (word, context) = (vocab.id(pair[0]), vocab.id(pair[1]));
+            let (estimate, error) = random_index.recover(matrix.slice(s![word..word + 1, ..]),
+                contexts.slice(s![first + context..first + context + 1, ..]), offset, contexts.rows());
+            println!("{}\t{}\t{}\t{}", pair[0], pair[1], estimate[[0, 0]], error[[0, 0]]);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,140 @@
+//! Query and merge count-min sketches of cooccurrences, from cb-cooccur --sketch
+//!
+//! A sketch can't list the pairs it counted, but it can estimate any of them:
+//!
+//! - query: read pairs from STDIN, one per line as a word and a context separated by a tab, and
+//!   print each back with its count-min estimate (never too low) and count-mean-min estimate
+//!   (closer for rare pairs, but it can be too low) added as two more columns
+//! - top: print the --top contexts (10 by default) with the largest estimates for each word, as
+//!   tab separated lines of the word, the context and the estimate
+//! - merge: add up sketches counted from chunks of a corpus with the same word list and options,
+//!   which gives the sketch of the whole corpus (sketches of other word lists, windows,
+//!   tokenizer settings or seeds are refused)
+//!
+//! The word list and window are read from the JSON file next to each sketch. When the window
+//! tells contexts apart by side or offset, --block picks which block the contexts are in (see the
+//! JSON file).
+//!
+
+// argument parsing
+#[macro_use] extern crate clap;
+// logging
+#[macro_use] extern crate log;
+extern crate env_logger;
+// lastly, this library
+extern crate cabarrus;
+
+use std::io::{self, BufRead};
+use clap::{AppSettings, ArgMatches, SubCommand};
+use cabarrus::errors::*;
+use cabarrus::meta::MatrixMeta;
+use cabarrus::options;
+use cabarrus::sketch::CountMinSketch;
+use cabarrus::vocab::Vocab;
+
+pub fn main() {
+    // Main can't return a Result, and the ? operator needs the enclosing function to return Result
+    inner_main().expect("Could not recover. Exiting.");
+}
+pub fn inner_main() -> Result<()> {
+    env_logger::init().unwrap();
+    let args = app_from_crate!()
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("query")
+            .about("estimate the counts of word and context pairs read from STDIN")
+            .arg_from_usage("--block=[N] 'which block of columns the contexts are in (default 0)'")
+            .arg_from_usage("<sketch> 'count-min sketch from cb-cooccur --sketch'"))
+        .subcommand(SubCommand::with_name("top")
+            .about("find the contexts with the largest counts for each word")
+            .arg_from_usage("--block=[N] 'which block of columns the contexts are in (default 0)'")
+            .arg_from_usage("--top=[N] 'how many contexts to find for each word (default 10)'")
+            .arg_from_usage("<sketch> 'count-min sketch from cb-cooccur --sketch'")
+            .arg_from_usage("<words>... 'words to find the contexts of'"))
+        .subcommand(SubCommand::with_name("merge")
+            .about("add up sketches of chunks of a corpus")
+            .arg_from_usage("<output> 'file in which to store the merged sketch'")
+            .arg_from_usage("<inputs>... 'sketches to merge, with the same word list and options'"))
+        .get_matches();
+
+    match args.subcommand() {
+        ("query", Some(args)) => {
+            let (sketch, vocab, first) = open(args)?;
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                let line = line?;
+                let pair: Vec<&str> = line.split('\t').collect();
+                if pair.len() != 2 {
+                    warn!("Skipping {:?}, which isn't a word and a context separated by a tab.", line);
+                    continue;
+                }
+                let (word, context) = (vocab.id(pair[0]), first + vocab.id(pair[1]));
+                println!("{}\t{}\t{}\t{}", pair[0], pair[1], sketch.estimate(word, context),
+                    sketch.estimate_mean_min(word, context));
+            }
+        },
+        ("top", Some(args)) => {
+            let (sketch, vocab, first) = open(args)?;
+            let top = options::value(args, "top")?.unwrap_or(10);
+            for word in args.values_of("words").unwrap() {
+                let contexts = first..first + vocab.len() + 1;
+                for (context, estimate) in sketch.top_contexts(vocab.id(word), contexts, top) {
+                    println!("{}\t{}\t{}", word, vocab.word(context - first).unwrap_or(""), estimate);
+                }
+            }
+        },
+        ("merge", Some(args)) => {
+            let inputs: Vec<&str> = args.values_of("inputs").unwrap().collect();
+            let mut sketch = CountMinSketch::read(inputs[0])?;
+            let mut meta = MatrixMeta::read_for(inputs[0])?;
+            let mut unknown = meta.tokens.unwrap_or(0) as f64 * meta.unknown_rate.unwrap_or(0.0);
+            for input in inputs[1..].iter() {
+                // Sketches of different words or contexts would add up to nonsense (and the
+                // sketches themselves refuse a different shape or seed)
+                let other = MatrixMeta::read_for(input)?;
+                if other.vocabulary != meta.vocabulary {
+                    return Err(Error::Other(format!(
+                        "{} was counted with a different word list than {}.", input, inputs[0])));
+                }
+                if other.window != meta.window || other.char_ngrams != meta.char_ngrams {
+                    return Err(Error::Other(format!(
+                        "{} was counted with a different window or n-grams than {}.", input, inputs[0])));
+                }
+                if other.tokenizer != meta.tokenizer {
+                    return Err(Error::Other(format!(
+                        "{} was tokenized differently than {}.", input, inputs[0])));
+                }
+                sketch.merge(&CountMinSketch::read(input)?)?;
+                let tokens = other.tokens.unwrap_or(0);
+                unknown += tokens as f64 * other.unknown_rate.unwrap_or(0.0);
+                meta.tokens = Some(meta.tokens.unwrap_or(0) + tokens);
+            }
+            meta.unknown_rate = meta.tokens.map(|tokens| unknown / ::std::cmp::max(1, tokens) as f64);
+            info!("Merged {} sketches, adding up to {}.", inputs.len(), sketch.total());
+            let output = args.value_of("output").unwrap();
+            sketch.write(output)?;
+            meta.write_for(output)?;
+        },
+        _ => unreachable!()
+    }
+    Ok(())
+}
+
+/// Read a sketch, its word list and the first column of the block of contexts wanted
+fn open(args: &ArgMatches) -> Result<(CountMinSketch, Vocab, usize)> {
+    let path = args.value_of("sketch").unwrap();
+    let sketch = CountMinSketch::read(path)?;
+    let meta = MatrixMeta::read_for(path)?;
+    let vocab = match meta.wordlist {
+        Some(ref wordlist) => Vocab::read(wordlist)?,
+        None => return Err(Error::Other(format!("{} doesn't say which word list the sketch uses.",
+            MatrixMeta::path_for(path).display())))
+    };
+    let block: usize = options::value(args, "block")?.unwrap_or(0);
+    let first = block * (vocab.len() + 1);
+    if first + vocab.len() + 1 > sketch.cols() {
+        return Err(Error::Other(format!("The sketch has no block {} of contexts.", block)));
+    }
+    info!("Estimates are at most {:.2} too high with {:.2}% confidence.", sketch.error_bound(),
+        100.0 * (1.0 - (-(sketch.depth() as f64)).exp()));
+    Ok((sketch, vocab, first))
+}
@@ -0,0 +1,88 @@
+//! Term-document matrix builder, for LSA and topic models
+//!
+//! This takes an input WET corpus piped to STDIN, numbers its records as documents in the order
+//! they come, and counts how often each word of a newline-separated list occurs in each one. The
+//! output is a sparse COO matrix (see `cabarrus::sparse` for the format) with a row for each word,
+//! starting with the unknown word as word 0, and a column for each document.
+//!
+//! The counts can be weighted (--weighting) by tf (raw counts, the default), tfidf, log-entropy or
+//! bm25 (with --k1 and --b). Words are found the same way as in cb-cooccur, so the same stopwords,
+//! phrases and subwords options apply. Words not in the list are counted as the unknown word 0
+//! unless they are dropped (--unknowns=drop) or skipped (--unknowns=skip), which leaves row 0
+//! empty but still counts them in the length of their document, for bm25.
+//!
+//! Next to the output, output.docs lists the documents, one per line: the document id, the
+//! WARC-Record-ID and the WARC-Target-URI, separated by tabs. output.json describes the matrix.
+//!
+
+// argument parsing
+#[macro_use] extern crate clap;
+// logging
+#[macro_use] extern crate log;
+extern crate env_logger;
+// lastly, this library
+extern crate cabarrus;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use cabarrus::warc::WarcStreamer;
+use cabarrus::errors::*;
+use cabarrus::meta::{Block, MatrixMeta};
+use cabarrus::options;
+use cabarrus::termdoc::{TermDocCounter, TermWeighting};
+use cabarrus::tokens::Unknowns;
+use cabarrus::vocab::Vocab;
+
+pub fn main() {
+    // Main can't return a Result, and the ? operator needs the enclosing function to return Result
+    inner_main().expect("Could not recover. Exiting.");
+}
+pub fn inner_main() -> Result<()> {
+    env_logger::init().unwrap();
+    let args = app_from_crate!()
+        .args_from_usage(options::PHRASES)
+        .args_from_usage(options::TOKENIZER)
+        .arg_from_usage("--weighting=[NAME] 'how to weight the counts: tf (default), tfidf, log-entropy or bm25'")
+        .arg_from_usage("--k1=[K] 'bm25 term frequency saturation (default 1.2)'")
+        .arg_from_usage("--b=[B] 'bm25 document length normalization, from 0 to 1 (default 0.75)'")
+        .arg_from_usage("<wordlist> 'file containing words to look for, one per line'")
+        .arg_from_usage("<output> 'file in which to store the resulting term-document matrix'")
+        .get_matches();
+
+    let mut tokenizer = options::tokenizer(&args, Vocab::read(args.value_of("wordlist").unwrap())?)?;
+    let vocab_size = tokenizer.vocab().len();
+    let mut weighting = options::value(&args, "weighting")?.unwrap_or(TermWeighting::Tf);
+    if let TermWeighting::Bm25 { ref mut k1, ref mut b } = weighting {
+        *k1 = options::value(&args, "k1")?.unwrap_or(*k1);
+        *b = options::value(&args, "b")?.unwrap_or(*b);
+    }
+    let output = args.value_of("output").unwrap();
+
+    // Number the documents as they come, and list them as we go
+    let mut counter = TermDocCounter::new(vocab_size);
+    let skip_unknowns = tokenizer.unknowns() == Unknowns::Skip;
+    let mut index = BufWriter::new(File::create(format!("{}.docs", output))?);
+    for rec in WarcStreamer::new()? {
+        let document = counter.add_document(&tokenizer.tokenize(&rec), skip_unknowns);
+        writeln!(index, "{}\t{}\t{}", document,
+            rec.id.as_ref().map(|id| &id[..]).unwrap_or(""),
+            rec.uri.as_ref().map(|uri| &uri[..]).unwrap_or(""))?;
+    }
+    index.flush()?;
+    info!("Counted {} words in {} documents, {:.2}% of tokens unknown.",
+        vocab_size, counter.documents(), 100.0 * tokenizer.unknown_rate());
+
+    let nnz = counter.write_coo(output, weighting)?;
+    info!("Wrote {} entries, weighted {:?}.", nnz, weighting);
+    let mut meta = MatrixMeta::new("cb-termdoc", counter.rows(), counter.documents());
+    meta.format = "coo".to_owned();
+    meta.wordlist = args.value_of("wordlist").map(String::from);
+    meta.tokenizer = Some(tokenizer.settings());
+    meta.row_blocks = Block::tile(&["word".to_owned()], counter.rows());
+    meta.column_blocks = Block::tile(&["document".to_owned()], counter.documents());
+    meta.weighting = Some(format!("{:?}", weighting));
+    meta.tokens = Some(tokenizer.looked_up());
+    meta.unknown_rate = Some(tokenizer.unknown_rate());
+    meta.write_for(output)?;
+    Ok(())
+}
@@ -1,26 +1,31 @@
-//! Example 0: Word cooccurrence counter
+//! Example 0: Token counter
 //!
-//! This simple script takes an input WET corpus piped to STDIN and counts cooccurrences of input
-//! tokens, where tokens are defined by unicode, and cooccurrence is a window 21 words in
-//! diameter, and only words from a newline-separated list are considered.
+//! This simple script takes an input WET corpus piped to STDIN and counts its tokens, where tokens
+//! are defined by unicode (leaving out whitespace), with phrases merged if there are any.
 //!
-//! The output is a numpy file with a cooccurrence matrix. The rows are the center word, starting
-//! with the unknown word as word 0, and proceeding in the order they were specified in the input
-//! word list. The columns are the context words, and cooccurrence is always counted as 1 or 0.
+//! Tokens are split the same way as in every other binary (see `cabarrus::tokens`), so the count
+//! is the number of tokens windows slide over.
+//!
+//! It prints just the number of tokens, but with --report it also writes a JSON report about the
+//! corpus: documents, tokens, types (estimated within about 1%), the type/token ratio, a histogram
+//! of document lengths, a Heaps' law growth curve and fit, and a Zipf's law fit to the most
+//! frequent tokens. Reports of chunks counted in parallel can be combined with --merge, which reads
+//! reports instead of a corpus.
 //!
 
+// argument parsing
+#[macro_use] extern crate clap;
 // logging
 #[macro_use] extern crate log;
 extern crate env_logger;
-// better segmentation
-extern crate unicode_segmentation;
 // lastly, this library
 extern crate cabarrus;
 
-use unicode_segmentation::UnicodeSegmentation;
-
 use cabarrus::warc::WarcStreamer;
 use cabarrus::errors::*;
+use cabarrus::options;
+use cabarrus::stats::{CorpusReport, CorpusStats};
+use cabarrus::tokens;
 
 pub fn main() {
     // Main can't return a Result, and the ? operator needs the enclosing function to return Result
@@ -28,11 +33,39 @@ pub fn main() {
 }
 pub fn inner_main() -> Result<()> {
     env_logger::init().unwrap();
+    let args = app_from_crate!()
+        .args_from_usage(options::PHRASES)
+        .arg_from_usage("--report=[FILE] 'write a JSON report about the corpus to FILE'")
+        .arg_from_usage("--top=[N] 'include the N most frequent tokens in the report (default 1000)'")
+        .arg_from_usage("--merge 'combine the given reports instead of reading a corpus'")
+        .arg_from_usage("[reports]... 'reports to combine, with --merge'")
+        .get_matches();
+    let phrases = options::phrases(&args)?;
+    let top = options::value(&args, "top")?.unwrap_or(1000);
+
+    let report = if args.is_present("merge") {
+        let mut paths = args.values_of("reports").into_iter().flat_map(|paths| paths);
+        let mut report = match paths.next() {
+            Some(path) => CorpusReport::read(path)?,
+            None => return Err(Error::Other("There are no reports to merge.".to_string()))
+        };
+        for path in paths {
+            info!("Merging {}", path);
+            report.merge(&CorpusReport::read(path)?)?;
+        }
+        report
+    } else {
+        let mut stats = CorpusStats::new(top);
+        for rec in WarcStreamer::new()? {
+            stats.add(&tokens::phrase_words(&rec, &phrases).collect::<Vec<_>>());
+        }
+        stats.report()
+    };
+    info!("{} documents, {} tokens, about {:.0} types.", report.documents, report.tokens, report.types);
     
-    let term_count :usize = WarcStreamer::new()?
-        .map(|rec| rec.split_word_bounds().count())
-        .sum();
-    
-    println!("{}", term_count);
+    println!("{}", report.tokens);
+    if let Some(path) = args.value_of("report") {
+        report.write(path)?;
+    }
     Ok(())
 }
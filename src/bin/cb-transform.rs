@@ -0,0 +1,80 @@
+//! Cooccurrence transforms: PMI, positive PMI, shifted PPMI, square root or log scaling
+//!
+//! This reads a cooccurrence matrix, dense (.npy, as cb-cooccur writes by default) or sparse (as
+//! cb-cooccur --sparse writes), and writes it reweighted, in the same format. See
+//! `cabarrus::transform` for the formulas. For PMI and PPMI, --shift=K subtracts ln K (shifted
+//! PPMI, like word2vec with K negative samples) and --alpha=0.75 smooths the context distribution.
+//!
+//! The input is never read into memory all at once: dense matrices are streamed through an mmap
+//! and sparse ones through the file, once to add up the marginals and once to transform. Dense
+//! output is written through an mmap too. If the input has a JSON file describing it, the output
+//! gets a copy that says how it was transformed.
+//!
+//! Only counts can be transformed. Inputs whose JSON file shows they are random projections (from
+//! cb-approx-cooccur), count-min sketches or weighted already are refused, and so is any negative
+//! entry, which would otherwise give NaN.
+//!
+
+// argument parsing
+#[macro_use] extern crate clap;
+// logging
+#[macro_use] extern crate log;
+extern crate env_logger;
+// lastly, this library
+extern crate cabarrus;
+
+use cabarrus::errors::*;
+use cabarrus::meta::MatrixMeta;
+use cabarrus::options;
+use cabarrus::transform::{self, Transform};
+
+pub fn main() {
+    // Main can't return a Result, and the ? operator needs the enclosing function to return Result
+    inner_main().expect("Could not recover. Exiting.");
+}
+pub fn inner_main() -> Result<()> {
+    env_logger::init().unwrap();
+    let args = app_from_crate!()
+        .arg_from_usage("--shift=[K] 'for pmi and ppmi, subtract ln K (default 1, no shift)'")
+        .arg_from_usage("--alpha=[A] 'for pmi and ppmi, raise context counts to this power (try 0.75; default 1, no smoothing)'")
+        .arg_from_usage("<transform> 'pmi, ppmi, sqrt or log'")
+        .arg_from_usage("<input> 'cooccurrence matrix to transform, dense or sparse'")
+        .arg_from_usage("<output> 'file in which to store the transformed matrix'")
+        .get_matches();
+    let transform: Transform = options::value(&args, "transform")?.unwrap();
+    let k = options::value(&args, "shift")?.unwrap_or(1.0);
+    let alpha = options::value(&args, "alpha")?.unwrap_or(1.0);
+    let input = args.value_of("input").unwrap();
+    let output = args.value_of("output").unwrap();
+
+    // Only counts can be reweighted, as far as the JSON file (if any) tells
+    let meta = if MatrixMeta::path_for(input).exists() { Some(MatrixMeta::read_for(input)?) } else { None };
+    if let Some(ref meta) = meta {
+        if meta.format == "cms" {
+            return Err(Error::Other(format!("{} is a count-min sketch, which can only be queried \
+                (see cb-sketch).", input)));
+        }
+        if meta.random_index.is_some() {
+            return Err(Error::Other(format!("{} holds random projections of counts, which aren't \
+                counts themselves.", input)));
+        }
+        if let Some(ref weighting) = meta.weighting {
+            return Err(Error::Other(format!("{} was weighted already ({}).", input, weighting)));
+        }
+    }
+
+    let (rows, cols) = transform::transform_file(input, output, transform, k, alpha)?;
+    info!("Transformed a {}x{} matrix by {:?}.", rows, cols, transform);
+
+    // Say what happened, keeping what's known about the input
+    if let Some(mut meta) = meta {
+        meta.program = "cb-transform".to_owned();
+        meta.weighting = Some(if transform.needs_marginals() {
+            format!("{:?}, shifted by ln {}, context counts raised to {}", transform, k, alpha)
+        } else {
+            format!("{:?}", transform)
+        });
+        meta.write_for(output)?;
+    }
+    Ok(())
+}
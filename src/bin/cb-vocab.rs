@@ -0,0 +1,92 @@
+//! Vocabulary builder: count every token in a corpus
+//!
+//! This takes an input WET corpus piped to STDIN and writes a word list of every distinct token,
+//! most frequent first, each followed by a tab and its count. Tokens are defined by unicode, the
+//! same way the cooccurrence counters define them, so the output works as their word list. That
+//! includes merging phrases (--phrases), which is how phrases get into the word list.
+//!
+//! Counting exactly needs memory for every distinct token, and on a full crawl the long tail of
+//! junk tokens makes that unbounded. With --memory, the counts are instead kept in a Space-Saving
+//! summary of a fixed size. Then counts can be overestimated, by at most the error that is logged
+//! at the end, but every token seen more often than that error is guaranteed to be in the list.
+//!
+
+// argument parsing
+#[macro_use] extern crate clap;
+// logging
+#[macro_use] extern crate log;
+extern crate env_logger;
+// lastly, this library
+extern crate cabarrus;
+
+use cabarrus::warc::WarcStreamer;
+use cabarrus::errors::*;
+use cabarrus::farm::{self, FarmMap};
+use cabarrus::options;
+use cabarrus::sketch::SpaceSaving;
+use cabarrus::tokens;
+use cabarrus::vocab::Vocab;
+
+pub fn main() {
+    // Main can't return a Result, and the ? operator needs the enclosing function to return Result
+    inner_main().expect("Could not recover. Exiting.");
+}
+pub fn inner_main() -> Result<()> {
+    env_logger::init().unwrap();
+    let args = app_from_crate!()
+        .args_from_usage(options::PHRASES)
+        .arg_from_usage("--memory=[MB] 'count approximately, in about this many megabytes'")
+        .arg_from_usage("--min-count=[N] 'leave out words seen fewer than N times'")
+        .arg_from_usage("--max-words=[N] 'keep only the N most frequent words'")
+        .arg_from_usage("<output> 'file in which to store the words and their counts'")
+        .get_matches();
+    let memory: Option<usize> = options::value(&args, "memory")?;
+    let min_count = options::value(&args, "min-count")?.unwrap_or(1);
+    let max_words = options::value(&args, "max-words")?.unwrap_or(usize::max_value());
+    let phrases = options::phrases(&args)?;
+
+    let mut counts: Vec<(String, u64)> = match memory {
+        Some(megabytes) => {
+            // Bounded memory, approximate counts
+            let mut summary = SpaceSaving::with_memory(megabytes << 20);
+            info!("Counting approximately, tracking at most {} distinct words.", summary.capacity());
+            for rec in WarcStreamer::new()? {
+                for word in tokens::phrase_words(&rec, &phrases) {
+                    summary.insert(&*word);
+                }
+            }
+            info!("Counted {} tokens. Counts are overestimated by at most {}, \
+                and every word seen more often than that is included.",
+                summary.total(), summary.max_error());
+            summary.into_sorted_vec().into_iter()
+                .map(|counter| (counter.item, counter.count))
+                .collect()
+        },
+        None => {
+            // Unbounded memory, exact counts
+            let mut exact: FarmMap<String, u64> = farm::new_farm();
+            for rec in WarcStreamer::new()? {
+                for word in tokens::phrase_words(&rec, &phrases) {
+                    // Only allocate for words we haven't seen yet
+                    if let Some(count) = exact.get_mut(&*word) {
+                        *count += 1;
+                        continue;
+                    }
+                    exact.insert(word.into_owned(), 1);
+                }
+            }
+            info!("Counted {} tokens exactly.", exact.values().sum::<u64>());
+            exact.into_iter().collect()
+        }
+    };
+
+    // Most frequent first, ties alphabetically so the output is repeatable
+    counts.sort_by(|a, b| (b.1, &a.0).cmp(&(a.1, &b.0)));
+    counts.retain(|&(_, count)| count >= min_count);
+    counts.truncate(max_words);
+    info!("Keeping {} words.", counts.len());
+
+    let (words, counts) = counts.into_iter().unzip();
+    Vocab::new(words, Some(counts)).write(args.value_of("output").unwrap())?;
+    Ok(())
+}
@@ -0,0 +1,381 @@
+//! Byte pair encoding (Sennrich, Haddow and Birch, 2016): an open vocabulary of subwords
+//!
+//! Words start out split into characters, and the most frequent pair of adjacent symbols is
+//! merged into a new symbol, over and over. Applying the same merges in the same order splits any
+//! word into known subwords, so the only unknowns left are characters never seen in training.
+//!
+//! Merge files have one merge per line, in the order they were learned: the two symbols separated
+//! by a space. The last symbol of every word ends with `END_OF_WORD`, which keeps the "low" of
+//! "lowest" apart from the word "low".
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use errors::*;
+use farm::{self, FarmMap, new_farm};
+use sketch::SpaceSaving;
+
+/// Marks the last symbol of a word
+pub const END_OF_WORD: &'static str = "</w>";
+
+/// Split a word into characters, the first symbols of every word
+fn characters(word: &str) -> Vec<String> {
+    let mut symbols: Vec<String> = word.chars().map(|c| c.to_string()).collect();
+    if let Some(last) = symbols.last_mut() {
+        last.push_str(END_OF_WORD);
+    }
+    symbols
+}
+
+/// A list of merges, in the order they apply
+pub struct Merges {
+    merges: Vec<(String, String)>,
+    /// First symbol -> second symbol -> when that merge applies
+    ranks: FarmMap<String, FarmMap<String, usize>>,
+}
+
+impl Merges {
+    pub fn new() -> Merges {
+        Merges { merges: vec![], ranks: new_farm() }
+    }
+
+    /// Add a merge after all the others
+    pub fn push(&mut self, first: &str, second: &str) {
+        let rank = self.merges.len();
+        self.ranks.entry(first.to_owned()).or_insert_with(new_farm)
+            .entry(second.to_owned()).or_insert(rank);
+        self.merges.push((first.to_owned(), second.to_owned()));
+    }
+
+    /// How many merges there are
+    pub fn len(&self) -> usize {
+        self.merges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.merges.is_empty()
+    }
+
+    /// A hash of the merges in order, which tells whether two lists split words the same way
+    pub fn fingerprint(&self) -> String {
+        let merges: Vec<String> = self.merges.iter()
+            .map(|&(ref first, ref second)| format!("{} {}", first, second))
+            .collect();
+        farm::fingerprint(&merges)
+    }
+
+    /// Read merges, as written by `write()`
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Merges> {
+        let file = File::open(path.as_ref())
+            .map_err(|e| Error::MissingFile("BPE merges file", Some(e)))?;
+        let mut merges = Merges::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let symbols: Vec<&str> = line.split(' ').collect();
+            if symbols.len() != 2 {
+                return Err(Error::Other(format!(
+                    "Expected BPE merges to be two symbols separated by a space, \
+                    but found {:?} in {}.", line, path.as_ref().display())));
+            }
+            merges.push(symbols[0], symbols[1]);
+        }
+        Ok(merges)
+    }
+
+    /// Write the merges, one per line, in order
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for &(ref first, ref second) in self.merges.iter() {
+            writeln!(writer, "{} {}", first, second)?;
+        }
+        Ok(())
+    }
+
+    /// When the merge of these two symbols applies, if there is one
+    fn rank(&self, first: &str, second: &str) -> Option<usize> {
+        self.ranks.get(first).and_then(|seconds| seconds.get(second)).cloned()
+    }
+
+    /// Split a word into subwords
+    pub fn encode(&self, word: &str) -> Vec<String> {
+        let mut symbols = characters(word);
+        while symbols.len() > 1 {
+            // Apply the earliest merge that is possible anywhere in the word
+            let earliest = (0..symbols.len() - 1)
+                .filter_map(|i| self.rank(&symbols[i], &symbols[i + 1]))
+                .min();
+            let (first, second) = match earliest {
+                Some(rank) => (&self.merges[rank].0, &self.merges[rank].1),
+                None => break
+            };
+            let mut merged = Vec::with_capacity(symbols.len());
+            let mut symbols_iter = symbols.into_iter().peekable();
+            while let Some(symbol) = symbols_iter.next() {
+                if &symbol == first && symbols_iter.peek() == Some(second) {
+                    merged.push(symbol + &symbols_iter.next().unwrap());
+                } else {
+                    merged.push(symbol);
+                }
+            }
+            symbols = merged;
+        }
+        symbols
+    }
+}
+
+/// Counts words, in bounded memory, to learn merges from
+pub struct BpeLearner {
+    words: SpaceSaving<String>,
+}
+
+impl BpeLearner {
+    /// Count words in about `bytes` of memory
+    pub fn with_memory(bytes: usize) -> BpeLearner {
+        BpeLearner { words: SpaceSaving::with_memory(bytes) }
+    }
+
+    /// Count one occurrence of a word
+    pub fn add(&mut self, word: &str) {
+        self.words.insert(word);
+    }
+
+    /// How many words have been counted
+    pub fn total(&self) -> u64 {
+        self.words.total()
+    }
+
+    /// Learn up to `limit` merges from the words seen at least `min_count` times
+    ///
+    /// Along with the merges, this returns every subword that encoding could give, with how often
+    /// the training words end up split into it, most frequent first, which makes a word list for
+    /// the subwords. That is every merged symbol, and every character seen, both on its own and
+    /// ending a word, even if none is left after merging, so that new words made of the same
+    /// characters never split into unknown subwords.
+    pub fn learn(&self, limit: usize, min_count: u64) -> (Merges, Vec<(String, u64)>) {
+        // Symbols are numbered to keep pairs cheap to hash and compare
+        let mut names: Vec<String> = vec![];
+        let mut ids: FarmMap<String, u32> = new_farm();
+        let mut words: Vec<(Vec<u32>, i64)> = vec![];
+        for counter in self.words.iter().filter(|counter| counter.count >= min_count) {
+            let symbols = characters(&counter.item).into_iter()
+                .map(|name| intern(&mut names, &mut ids, name))
+                .collect();
+            words.push((symbols, counter.count as i64));
+            // Any character could end a new word, or come before others
+            for c in counter.item.chars() {
+                intern(&mut names, &mut ids, c.to_string());
+                intern(&mut names, &mut ids, c.to_string() + END_OF_WORD);
+            }
+        }
+
+        // How often each pair occurs, and in which words
+        let mut pair_counts: FarmMap<(u32, u32), i64> = new_farm();
+        let mut pair_words: FarmMap<(u32, u32), Vec<usize>> = new_farm();
+        for (w, &(ref symbols, count)) in words.iter().enumerate() {
+            for pair in symbols.windows(2) {
+                *pair_counts.entry((pair[0], pair[1])).or_insert(0) += count;
+                pair_words.entry((pair[0], pair[1])).or_insert_with(Vec::new).push(w);
+            }
+        }
+        // Max-heap of pairs by count. Counts change as merges happen, so entries can be stale,
+        // but every change pushes a fresh entry, and stale ones are skipped when they come up.
+        let mut heap: BinaryHeap<(i64, (u32, u32))> = pair_counts.iter()
+            .map(|(&pair, &count)| (count, pair))
+            .collect();
+
+        let mut merges = Merges::new();
+        while merges.len() < limit {
+            let (count, pair) = match heap.pop() {
+                Some(entry) => entry,
+                None => break
+            };
+            if pair_counts.get(&pair) != Some(&count) {
+                continue; // stale
+            }
+            if count < 2 {
+                break; // nothing left is worth merging
+            }
+            let name = names[pair.0 as usize].clone() + &names[pair.1 as usize];
+            let merged = intern(&mut names, &mut ids, name);
+            merges.push(&names[pair.0 as usize], &names[pair.1 as usize]);
+
+            let mut affected = pair_words.remove(&pair).unwrap_or_default();
+            affected.sort();
+            affected.dedup();
+            let mut changed = vec![];
+            for w in affected {
+                let (ref mut symbols, count) = words[w];
+                let replacement = merge_pair(symbols, pair, merged);
+                if replacement.len() == symbols.len() {
+                    continue; // the pair was already merged away in this word
+                }
+                for old in symbols.windows(2) {
+                    *pair_counts.get_mut(&(old[0], old[1])).unwrap() -= count;
+                    changed.push((old[0], old[1]));
+                }
+                for new in replacement.windows(2) {
+                    *pair_counts.entry((new[0], new[1])).or_insert(0) += count;
+                    pair_words.entry((new[0], new[1])).or_insert_with(Vec::new).push(w);
+                    changed.push((new[0], new[1]));
+                }
+                *symbols = replacement;
+            }
+            changed.sort();
+            changed.dedup();
+            for pair in changed {
+                heap.push((pair_counts[&pair], pair));
+            }
+        }
+        info!("Learned {} merges, making {} symbols.", merges.len(), names.len());
+
+        // Every symbol, with how often the training words are made of it now
+        let mut symbol_counts = vec![0; names.len()];
+        for &(ref symbols, count) in words.iter() {
+            for &symbol in symbols {
+                symbol_counts[symbol as usize] += count as u64;
+            }
+        }
+        let mut subwords: Vec<(String, u64)> = names.into_iter().zip(symbol_counts).collect();
+        subwords.sort_by(|a, b| (b.1, &a.0).cmp(&(a.1, &b.0)));
+        (merges, subwords)
+    }
+}
+
+/// Get the id of a symbol, numbering it if it's new
+fn intern(names: &mut Vec<String>, ids: &mut FarmMap<String, u32>, name: String) -> u32 {
+    if let Some(&id) = ids.get(&name) {
+        return id;
+    }
+    let id = names.len() as u32;
+    ids.insert(name.clone(), id);
+    names.push(name);
+    id
+}
+
+/// Replace every occurrence of a pair of symbols with their merged symbol
+fn merge_pair(symbols: &[u32], pair: (u32, u32), merged: u32) -> Vec<u32> {
+    let mut replacement = Vec::with_capacity(symbols.len());
+    let mut i = 0;
+    while i < symbols.len() {
+        if i + 1 < symbols.len() && (symbols[i], symbols[i + 1]) == pair {
+            replacement.push(merged);
+            i += 2;
+        } else {
+            replacement.push(symbols[i]);
+            i += 1;
+        }
+    }
+    replacement
+}
+
+/// Splits words into subword ids, remembering the words it has seen
+pub struct SubwordEncoder {
+    merges: Merges,
+    cache: FarmMap<String, Vec<String>>,
+}
+
+/// Forget the cached words after this many, so memory stays bounded
+const CACHE_LIMIT: usize = 1 << 20;
+
+impl SubwordEncoder {
+    pub fn new(merges: Merges) -> SubwordEncoder {
+        SubwordEncoder { merges: merges, cache: new_farm() }
+    }
+
+    /// The subwords of a word, using the cache when possible
+    pub fn encode(&mut self, word: &str) -> &[String] {
+        if !self.cache.contains_key(word) {
+            if self.cache.len() >= CACHE_LIMIT {
+                self.cache.clear();
+            }
+            let subwords = self.merges.encode(word);
+            self.cache.insert(word.to_owned(), subwords);
+        }
+        &self.cache[word]
+    }
+
+    /// How many merges are applied
+    pub fn len(&self) -> usize {
+        self.merges.len()
+    }
+
+    /// The fingerprint of the merges applied (see `Merges::fingerprint()`)
+    pub fn fingerprint(&self) -> String {
+        self.merges.fingerprint()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.merges.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Learn from each word repeated as many times as given
+    fn learn(words: &[(&str, usize)], limit: usize) -> (Merges, Vec<(String, u64)>) {
+        let mut learner = BpeLearner::with_memory(1 << 20);
+        for &(word, times) in words {
+            for _ in 0..times {
+                learner.add(word);
+            }
+        }
+        learner.learn(limit, 1)
+    }
+
+    #[test]
+    fn encode_round_trips() {
+        let (merges, _) = learn(&[("lower", 5), ("lowest", 3), ("newer", 4), ("wider", 2)], 10);
+        assert!(!merges.is_empty());
+        for word in ["lower", "lowest", "newer", "wider", "slow", "w", "ñandú"].iter() {
+            let subwords = merges.encode(word);
+            assert!(subwords.last().unwrap().ends_with(END_OF_WORD), "{:?}", subwords);
+            let joined: String = subwords.concat();
+            assert_eq!(joined, format!("{}{}", word, END_OF_WORD));
+        }
+        assert_eq!(merges.encode(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn merges_apply_in_order() {
+        // "a b" is the most frequent pair (8), then "ab c</w>" (5)
+        let (merges, _) = learn(&[("abc", 5), ("abd", 3)], 2);
+        assert_eq!(merges.len(), 2);
+        let strings = |subwords: &[&str]| subwords.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(merges.encode("abc"), strings(&["abc</w>"]));
+        assert_eq!(merges.encode("abd"), strings(&["ab", "d</w>"]));
+        // A "b" ending a word is a different symbol, so "a b</w>" isn't merged
+        assert_eq!(merges.encode("cab"), strings(&["c", "a", "b</w>"]));
+    }
+
+    #[test]
+    fn encoder_matches_merges() {
+        let (merges, _) = learn(&[("banana", 4), ("bandana", 2)], 8);
+        let expected: Vec<Vec<String>> = ["banana", "bandana", "nab"].iter()
+            .map(|word| merges.encode(word))
+            .collect();
+        let mut encoder = SubwordEncoder::new(merges);
+        for (word, expected) in ["banana", "bandana", "nab"].iter().zip(expected) {
+            assert_eq!(encoder.encode(word), &expected[..]);
+            // and again, from the cache
+            assert_eq!(encoder.encode(word), &expected[..]);
+        }
+    }
+
+    #[test]
+    fn subword_list_covers_new_words() {
+        // "x" only ever comes before "a", so after merging it's never on its own
+        let (merges, subwords) = learn(&[("xa", 10), ("ax", 1)], 1);
+        assert_eq!(merges.encode("xa"), vec!["xa</w>".to_owned()]);
+        let listed: Vec<&str> = subwords.iter().map(|&(ref subword, _)| subword.as_str()).collect();
+        for word in ["xa", "ax", "xx", "aax", "xax"].iter() {
+            for subword in merges.encode(word) {
+                assert!(listed.contains(&subword.as_str()), "{} of {} isn't listed", subword, word);
+            }
+        }
+        // Symbols that nothing is split into any more are listed, with no occurrences
+        assert!(subwords.contains(&("a</w>".to_owned(), 0)));
+        assert!(subwords.contains(&("xa</w>".to_owned(), 10)));
+    }
+}
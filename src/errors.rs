@@ -9,6 +9,7 @@ use ndarray_linalg;
 use std::fmt;
 use linxal::svd as lxsvd;
 use ndarray as nd;
+use serde_json;
 
 /// Type alias for Iredell errors
 pub type Result<X> = result::Result<X, Error>;
@@ -22,6 +23,7 @@ pub enum Error {
     ShapeError(nd::ShapeError),
     IOError(io::Error),
     ParseFloatError(num::ParseFloatError),
+    JSONError(serde_json::Error),
     MissingFile(&'static str, Option<io::Error>),
     Other(String),
 }
@@ -35,6 +37,7 @@ impl fmt::Display for Error {
             Error::ShapeError(ref err) => write!(f, "NDArray shape error: {:?}", err),
             Error::IOError(ref err) => write!(f, "IO error: {}", err),
             Error::ParseFloatError(ref err) => write!(f, "Error parsing float: {}", err),
+            Error::JSONError(ref err) => write!(f, "JSON error: {}", err),
             Error::MissingFile(ref info, ref opt_err) => {
                 write!(f,
                     "The {} must already exist at this point but there was a problem opening it. \
@@ -59,6 +62,7 @@ impl error::Error for Error {
             Error::ShapeError(ref err) => err.description(),
             Error::IOError(ref err) => err.description(),
             Error::ParseFloatError(ref err) => err.description(),
+            Error::JSONError(ref err) => err.description(),
             Error::MissingFile(ref info, _) => info,
             Error::Other(ref info) => info,
         }
@@ -72,6 +76,7 @@ impl error::Error for Error {
             Error::ShapeError(ref err) => Some(err),
             Error::IOError(ref err) => Some(err),
             Error::ParseFloatError(ref err) => Some(err),
+            Error::JSONError(ref err) => Some(err),
             Error::MissingFile(_, _) => None,
             Error::Other(_) => None,
         }
@@ -105,6 +110,11 @@ impl From<num::ParseFloatError> for Error {
         Error::ParseFloatError(err)
     }
 }
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::JSONError(err)
+    }
+}
 
 //
 // Convert Error into a general io Error
@@ -6,8 +6,12 @@ use std::hash::{Hash, Hasher, BuildHasherDefault};
 
 /// Act like a farmhash
 ///
-/// But since farmhash isn't a streaming hash we only compute the last bytes
-/// so it's not really fulfilling the Hasher trait. But it's enough for us.
+/// But since farmhash isn't a streaming hash, each write is hashed on its own and seeded with the
+/// hash so far. So it's not really fulfilling the Hasher trait (the result depends on how the
+/// bytes are split into writes) but it's enough for us. Chaining matters because `str` hashes as
+/// its bytes followed by a separate 0xff write, which would otherwise be all that's left: hashing
+/// only the last write put every string key in the same bucket, and every tuple key with the same
+/// last field.
 pub struct FarmHashLie (u64);
 
 impl Default for FarmHashLie {
@@ -22,7 +26,7 @@ impl Hasher for FarmHashLie {
     }
     #[inline]
     fn write(&mut self, bytes: &[u8]) {
-        self.0 = farmhash::hash64(bytes);
+        self.0 = farmhash::hash64_with_seed(bytes, self.0);
     }
 }
 
@@ -33,5 +37,40 @@ pub fn new_farm<X: Hash+Eq, Y>() -> FarmMap<X, Y> {
     Default::default()
 }
 
+/// A hash of some strings in order, to tell whether two lists are the same without keeping them
+pub fn fingerprint<S: AsRef<str>>(items: &[S]) -> String {
+    let hash = items.iter()
+        .fold(0, |hash, item| farmhash::hash64_with_seed(item.as_ref().as_bytes(), hash));
+    format!("{:016x}", hash)
+}
+
+
+pub type PlainMap<X, Y> = HashMap<X, Y, HashBuildHasher>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash<H: Hash + ?Sized>(item: &H) -> u64 {
+        let mut hasher = FarmHashLie::default();
+        item.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn every_write_counts() {
+        assert!(hash("cat") != hash("dog"));
+        assert!(hash(&(1u32, 2u32)) != hash(&(3u32, 2u32)));
+        assert!(hash(&("a", "bc")) != hash(&("ab", "c")));
+        // But it's still a function of the key
+        assert_eq!(hash("cat"), hash(&"cat".to_owned()));
+    }
 
-pub type PlainMap<X, Y> = HashMap<X, Y, HashBuildHasher>;
\ No newline at end of file
+    #[test]
+    fn string_keys_spread_out() {
+        let hashes: ::std::collections::HashSet<u64> = (0..10000)
+            .map(|i| hash(&format!("word{}", i)))
+            .collect();
+        assert_eq!(hashes.len(), 10000);
+    }
+}
@@ -6,7 +6,8 @@
 
 #[macro_use] extern crate log;
 #[macro_use] extern crate nom;
-extern crate ndarray;
+extern crate clap;
+#[macro_use] extern crate ndarray;
 extern crate farmhash;
 extern crate hash_hasher;
 extern crate linxal;
@@ -14,7 +15,26 @@ extern crate ndarray_linalg;
 extern crate byteorder;
 extern crate regex;
 extern crate memmap;
+extern crate rand;
+extern crate serde;
+#[macro_use] extern crate serde_derive;
+extern crate serde_json;
+extern crate unicode_segmentation;
 pub mod warc;
 pub mod farm;
 pub mod numpy;
-pub mod errors;
\ No newline at end of file
+pub mod errors;
+pub mod bpe;
+pub mod meta;
+pub mod ngrams;
+pub mod options;
+pub mod phrases;
+pub mod randindex;
+pub mod sketch;
+pub mod sparse;
+pub mod stats;
+pub mod termdoc;
+pub mod tokens;
+pub mod transform;
+pub mod vocab;
+pub mod window;
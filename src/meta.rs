@@ -0,0 +1,180 @@
+//! Metadata about the matrices binaries write, kept in a JSON file next to each matrix
+//!
+//! A matrix is just numbers, so this records what its rows and columns mean and how it was
+//! counted. The metadata of `output.npy` is in `output.npy.json`.
+//!
+//! A dense matrix can be added to later with counts from new input, as long as they are counted
+//! the same way (see `MatrixMeta::check_update()`). The names of the inputs counted so far are
+//! kept, so that none is counted twice.
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use serde_json;
+use errors::*;
+use ngrams::CharNgrams;
+use randindex::RandomIndex;
+use tokens::TokenizerSettings;
+use window::Window;
+
+/// A range of rows or columns that mean the same kind of thing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Block {
+    /// What these rows or columns are
+    pub label: String,
+    /// The first row or column of the block
+    pub start: usize,
+    /// How many rows or columns are in the block
+    pub width: usize,
+}
+
+impl Block {
+    /// Blocks of the same width, one after another, one for each label
+    pub fn tile(labels: &[String], width: usize) -> Vec<Block> {
+        labels.iter().enumerate()
+            .map(|(i, label)| Block { label: label.clone(), start: i * width, width: width })
+            .collect()
+    }
+}
+
+/// What a matrix contains
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatrixMeta {
+    /// The binary that wrote the matrix
+    pub program: String,
+    /// How the matrix is stored: npy (dense), coo (sparse) or cms (count-min sketch)
+    pub format: String,
+    pub rows: usize,
+    pub columns: usize,
+    /// The word list the rows (and maybe columns) are numbered by, with the unknown word as 0
+    pub wordlist: Option<String>,
+    /// The fingerprint of the word list (see `Vocab::fingerprint()`)
+    pub vocabulary: Option<String>,
+    /// How words were turned into tokens before they were looked up in the word list
+    pub tokenizer: Option<TokenizerSettings>,
+    /// The context window cooccurrences were counted in
+    pub window: Option<Window>,
+    /// The random context vectors, if contexts were randomly indexed
+    pub random_index: Option<RandomIndex>,
+    /// The character n-grams whose rows follow the words', if any
+    pub char_ngrams: Option<CharNgrams>,
+    /// How the entries were weighted, if they aren't plain counts
+    pub weighting: Option<String>,
+    /// How many tokens were looked up in the word list
+    pub tokens: Option<u64>,
+    /// The fraction of those tokens that weren't in the word list
+    pub unknown_rate: Option<f64>,
+    /// How many cycles of reflective random indexing made the matrix
+    pub cycles: Option<usize>,
+    /// The names of the inputs counted into the matrix, oldest first, as far as they were named
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    /// Layout of the rows
+    pub row_blocks: Vec<Block>,
+    /// Layout of the columns
+    pub column_blocks: Vec<Block>,
+}
+
+impl MatrixMeta {
+    /// Metadata for a matrix of a single block of rows and one of columns
+    pub fn new(program: &str, rows: usize, columns: usize) -> MatrixMeta {
+        MatrixMeta {
+            program: program.to_owned(),
+            format: "npy".to_owned(),
+            rows: rows,
+            columns: columns,
+            wordlist: None,
+            vocabulary: None,
+            tokenizer: None,
+            window: None,
+            random_index: None,
+            char_ngrams: None,
+            weighting: None,
+            tokens: None,
+            unknown_rate: None,
+            cycles: None,
+            inputs: vec![],
+            row_blocks: vec![Block { label: "row".to_owned(), start: 0, width: rows }],
+            column_blocks: vec![Block { label: "column".to_owned(), start: 0, width: columns }],
+        }
+    }
+
+    /// Where the metadata of the matrix at `matrix` is kept
+    pub fn path_for<P: AsRef<Path>>(matrix: P) -> PathBuf {
+        let mut path = matrix.as_ref().as_os_str().to_owned();
+        path.push(".json");
+        PathBuf::from(path)
+    }
+
+    /// Read the metadata of the matrix at `matrix`
+    pub fn read_for<P: AsRef<Path>>(matrix: P) -> Result<MatrixMeta> {
+        let file = File::open(MatrixMeta::path_for(matrix))
+            .map_err(|e| Error::MissingFile("matrix metadata", Some(e)))?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Check that new counts described by this metadata can be added to an existing matrix
+    ///
+    /// They have to be counted by the same program in the same way, into a dense matrix of the same
+    /// shape, with a word list that numbers words the same way and the same phrases, subwords,
+    /// stopwords, subsampling and unknown words, and none of their inputs can have been counted
+    /// already.
+    pub fn check_update(&self, existing: &MatrixMeta) -> Result<()> {
+        let refuse = |why: String| Err(Error::Other(format!("Can't add to the existing matrix: {}", why)));
+        if existing.format != "npy" || self.format != "npy" {
+            return refuse("only dense (npy) matrices can be added to.".to_owned());
+        }
+        if existing.program != self.program {
+            return refuse(format!("it was made by {}, not {}.", existing.program, self.program));
+        }
+        if (existing.rows, existing.columns) != (self.rows, self.columns) {
+            return refuse(format!("it's {}x{}, not {}x{}.",
+                existing.rows, existing.columns, self.rows, self.columns));
+        }
+        if existing.vocabulary.is_none() || existing.vocabulary != self.vocabulary {
+            return refuse(format!("it was counted with a different word list ({}), or it doesn't \
+                say which.", existing.wordlist.as_ref().map(|w| w.as_str()).unwrap_or("unknown")));
+        }
+        if existing.tokenizer != self.tokenizer {
+            return refuse(format!("its words were tokenized differently: {:?}.", existing.tokenizer));
+        }
+        if existing.window != self.window {
+            return refuse(format!("it was counted with a different window: {:?}.", existing.window));
+        }
+        if existing.random_index != self.random_index {
+            return refuse(format!("its random context vectors were made differently (seed {}).",
+                existing.random_index.as_ref().map(|r| r.seed.to_string()).unwrap_or("none".to_owned())));
+        }
+        if existing.char_ngrams != self.char_ngrams {
+            return refuse(format!("it has rows for different character n-grams: {:?}.",
+                existing.char_ngrams));
+        }
+        if existing.cycles.is_some() {
+            return refuse("it was made by reflective cycles, which can't be added to.".to_owned());
+        }
+        for input in self.inputs.iter() {
+            if existing.inputs.contains(input) {
+                return refuse(format!("{} was counted into it already.", input));
+            }
+        }
+        Ok(())
+    }
+
+    /// Combine the record of new counts with the existing matrix's they were added to
+    pub fn absorb(&mut self, existing: MatrixMeta) {
+        let (old, new) = (existing.tokens.unwrap_or(0), self.tokens.unwrap_or(0));
+        if let (Some(old_rate), Some(new_rate)) = (existing.unknown_rate, self.unknown_rate) {
+            self.unknown_rate = Some((old_rate * old as f64 + new_rate * new as f64)
+                / ::std::cmp::max(1, old + new) as f64);
+        }
+        self.tokens = Some(old + new);
+        let mut inputs = existing.inputs;
+        inputs.extend(self.inputs.drain(..));
+        self.inputs = inputs;
+    }
+
+    /// Write the metadata of the matrix at `matrix`, next to it
+    pub fn write_for<P: AsRef<Path>>(&self, matrix: P) -> Result<()> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(MatrixMeta::path_for(matrix))?), self)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+//! Character n-grams: the pieces of words that carry their morphology, like fastText's
+//!
+//! Each word is wrapped in boundary markers, `<` and `>`, so that prefixes and suffixes are told
+//! apart from the same letters in the middle of a word: "<wh" starts "where", but "wh" is in
+//! "nowhere" too. With n from 3 to 6, "where" has the n-grams "<wh", "whe", "her", "ere", "re>",
+//! "<whe" and so on up to "here>". The whole wrapped word isn't one of them, since the word has
+//! its own row. A marker on its own isn't either, when n starts at 1.
+//!
+//! There are far too many distinct n-grams to give each a row, so they are hashed into a fixed
+//! number of buckets, and n-grams that collide share one. Each bucket's row then adds up the
+//! contexts of every word containing its n-grams, and a vector for any word, even one never seen
+//! before, is the average of the rows of its n-grams (Bojanowski et al., 2017).
+use farmhash;
+use errors::*;
+
+/// What marks the start of a word in its n-grams
+pub const START: char = '<';
+/// What marks the end of a word in its n-grams
+pub const END: char = '>';
+
+/// Which n-grams words are split into, and how many buckets they are hashed into
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CharNgrams {
+    /// The shortest n-grams, in characters (including the markers)
+    pub min: usize,
+    /// The longest n-grams
+    pub max: usize,
+    /// How many buckets the n-grams are hashed into
+    pub buckets: usize,
+}
+
+impl CharNgrams {
+    /// N-grams with lengths in a range like "3-6" (or just "3"), hashed into `buckets` buckets
+    pub fn from_range(range: &str, buckets: usize) -> Result<CharNgrams> {
+        let bad = |e: ::std::num::ParseIntError| Error::Other(
+            format!("Couldn't understand {:?} as a range of n-gram lengths like 3-6: {}", range, e));
+        let (min, max) = match range.find('-') {
+            Some(dash) => (range[..dash].parse().map_err(&bad)?, range[dash + 1..].parse().map_err(&bad)?),
+            None => { let n = range.parse().map_err(&bad)?; (n, n) }
+        };
+        if min < 1 || min > max {
+            return Err(Error::Other(format!(
+                "N-grams need lengths from at least 1 up, not from {} to {}.", min, max)));
+        }
+        if buckets == 0 {
+            return Err(Error::Other("N-grams need at least one bucket.".to_owned()));
+        }
+        Ok(CharNgrams { min: min, max: max, buckets: buckets })
+    }
+
+    /// The n-grams of a word, shortest first, and from the start of the word among equals
+    pub fn ngrams(&self, word: &str) -> Vec<String> {
+        let chars: Vec<char> = Some(START).into_iter().chain(word.chars()).chain(Some(END)).collect();
+        let mut ngrams = vec![];
+        for n in self.min..self.max + 1 {
+            // The whole wrapped word is the word itself
+            if n >= chars.len() {
+                break;
+            }
+            for start in 0..chars.len() - n + 1 {
+                if n == 1 && (start == 0 || start == chars.len() - 1) {
+                    continue;
+                }
+                ngrams.push(chars[start..start + n].iter().cloned().collect());
+            }
+        }
+        ngrams
+    }
+
+    /// The buckets of a word's n-grams, with repeats if n-grams repeat or collide
+    pub fn buckets(&self, word: &str) -> Vec<usize> {
+        self.ngrams(word).iter()
+            .map(|ngram| (farmhash::hash64(ngram.as_bytes()) % self.buckets as u64) as usize)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ngrams_have_boundary_markers() {
+        let ngrams = CharNgrams::from_range("3-6", 100).unwrap();
+        assert_eq!(ngrams.ngrams("where"), vec![
+            "<wh", "whe", "her", "ere", "re>",
+            "<whe", "wher", "here", "ere>",
+            "<wher", "where", "here>",
+            "<where", "where>"]);
+        // Characters, not bytes
+        assert_eq!(CharNgrams::from_range("3", 100).unwrap().ngrams("né"), vec!["<né", "né>"]);
+    }
+
+    #[test]
+    fn ngrams_leave_out_the_whole_word_and_bare_markers() {
+        let ngrams = CharNgrams::from_range("1-3", 100).unwrap();
+        assert_eq!(ngrams.ngrams("a"), vec!["a", "<a", "a>"]);
+        assert!(ngrams.ngrams("").is_empty());
+        // Too long for any n-gram shorter than the wrapped word
+        assert!(CharNgrams::from_range("5-6", 100).unwrap().ngrams("ab").is_empty());
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(CharNgrams::from_range("3-6", 10).unwrap(),
+            CharNgrams { min: 3, max: 6, buckets: 10 });
+        assert_eq!(CharNgrams::from_range("4", 10).unwrap(),
+            CharNgrams { min: 4, max: 4, buckets: 10 });
+        for range in ["0-3", "6-3", "x", "3-", ""].iter() {
+            assert!(CharNgrams::from_range(range, 10).is_err(), "{:?} was accepted.", range);
+        }
+        assert!(CharNgrams::from_range("3-6", 0).is_err());
+    }
+
+    #[test]
+    fn shared_ngrams_share_buckets() {
+        let ngrams = CharNgrams::from_range("3-4", 1000).unwrap();
+        let where_ = ngrams.buckets("where");
+        let what = ngrams.buckets("what");
+        assert_eq!(where_.len(), ngrams.ngrams("where").len());
+        assert!(where_.iter().chain(what.iter()).all(|&bucket| bucket < 1000));
+        // Both start with "<wh", and the same n-gram always lands in the same bucket
+        assert_eq!(where_[0], what[0]);
+        assert_eq!(ngrams.buckets("where"), where_);
+        // With one bucket, everything collides
+        let one = CharNgrams::from_range("3-4", 1).unwrap();
+        assert_eq!(one.buckets("where"), vec![0; 9]);
+    }
+}
@@ -116,30 +116,48 @@ pub fn read_matrix<P: AsRef<Path>>(path: P) -> Result<Array2<f64>> {
 
 pub struct MatFile(usize, usize, Mmap);
 
+/// A numpy file mapped read-only, which can only be viewed, not written to
+pub struct ReadOnlyMatFile(MatFile);
+
 /// Load a Numpy matrix as an mmap. This only consumes address space. (Part 1)
 ///
 /// This is a two-step process because the Mmap needs to outlive the matrix.
 pub fn open_matrix_mmap<P: AsRef<Path>>(path: P) -> Result<MatFile> {
-    let header_match = Regex::new(r"NUMPY\x01\x00(?s:..)\{'descr': ?'<f8', ?'fortran_order': ?False, ?'shape': ?\((\d+), ?(\d+)\)\} *\n").unwrap();
-    let mut reader = OpenOptions::new()
+    let reader = OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
         .open(path.as_ref())?;
+    mmap_matrix(path.as_ref(), reader, Protection::ReadWrite)
+}
+
+/// Load a Numpy matrix as a read-only mmap (Part 1)
+///
+/// Unlike `open_matrix_mmap()`, the file isn't opened for writing, so it has to exist already and
+/// can be read-only. Read it with `view_matrix_mmap()`.
+pub fn open_matrix_mmap_readonly<P: AsRef<Path>>(path: P) -> Result<ReadOnlyMatFile> {
+    let reader = File::open(path.as_ref())
+        .map_err(|e| Error::MissingFile("matrix", Some(e)))?;
+    Ok(ReadOnlyMatFile(mmap_matrix(path.as_ref(), reader, Protection::Read)?))
+}
+
+/// Parse the header of an open numpy file and map the array after it
+fn mmap_matrix(path: &Path, mut reader: File, protection: Protection) -> Result<MatFile> {
+    let header_match = Regex::new(r"NUMPY\x01\x00(?s:..)\{'descr': ?'<f8', ?'fortran_order': ?False, ?'shape': ?\((\d+), ?(\d+)\)\} *\n").unwrap();
     let mut content = [0u8; 128];
     let bytes_read = reader.read(&mut content)?;
-    assert!(bytes_read > 0, format!("The numpy file {} seems to be empty.", path.as_ref().display())); 
+    assert!(bytes_read > 0, format!("The numpy file {} seems to be empty.", path.display())); 
     // The skip and the nested context here is so that we can regex parse (with a borrow)
     // and then reuse the buffer as the array. This way there are never two copies in memory.
     let captures = header_match.captures(&content)
-        .ok_or(helpful_complaint(path.as_ref(), &content))?;
+        .ok_or(helpful_complaint(path, &content))?;
     // where the full match ends
     let skip = captures.get(0).unwrap().end();
     // The shape of the array as described in the metadata
     let h = str::from_utf8(&captures[1]).unwrap().parse().unwrap();
     let w = str::from_utf8(&captures[2]).unwrap().parse().unwrap();
     
-    Ok(MatFile(h, w, Mmap::open_with_offset(&reader, Protection::ReadWrite, skip, h*w*8)?))
+    Ok(MatFile(h, w, Mmap::open_with_offset(&reader, protection, skip, h*w*8)?))
 }
 
 /// Load a Numpy matrix as an mmap (Part 2)
@@ -157,6 +175,18 @@ pub fn read_matrix_mmap<'t>(mmap: &'t MatFile) -> Result<ArrayViewMut2<'t, f64>>
     }
 }
 
+/// Load a Numpy matrix as a read-only mmap (Part 2)
+///
+/// Like `read_matrix_mmap()`, but for a file from `open_matrix_mmap_readonly()`, so the array
+/// can't be written to.
+pub fn view_matrix_mmap<'t>(mmap: &'t ReadOnlyMatFile) -> Result<ArrayView2<'t, f64>> {
+    let mmap = &mmap.0;
+    unsafe {
+        let new_slice = ::std::slice::from_raw_parts(mmap.2.ptr() as *const f64, mmap.2.len()/8);
+        Ok(ArrayView2::from_shape([mmap.0, mmap.1], new_slice)?)
+    }
+}
+
 /// Tell the user more info about the file
 ///
 /// It seems verbose but you can see this error often so it save you time.
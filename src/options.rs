@@ -0,0 +1,167 @@
+//! Command line options shared by several binaries
+//!
+//! Binaries add these with `.args_from_usage(options::TOKENIZER)` and read them back with the
+//! matching function, so that an option means the same thing in every binary.
+use std::fmt::Display;
+use std::str::FromStr;
+use std::path::Path;
+use clap::ArgMatches;
+use bpe::{Merges, SubwordEncoder};
+use errors::*;
+use meta::MatrixMeta;
+use phrases::PhraseTable;
+use randindex::{Distribution, Normalization, Permutation, RandomIndex};
+use tokens::{self, Tokenizer};
+use vocab::Vocab;
+use window::{Boundary, Contexts, Weighting, Window};
+
+/// Options for `phrases()`
+pub const PHRASES: &'static str =
+    "--phrases=[FILE]... 'merge the phrases in this table (from cb-phrases) into single tokens. Repeat as --phrases=FILE for more passes'";
+
+/// Read the phrase tables configured by the `PHRASES` options, in the order they apply
+pub fn phrases(args: &ArgMatches) -> Result<Vec<PhraseTable>> {
+    let mut tables = vec![];
+    for path in args.values_of("phrases").into_iter().flat_map(|paths| paths) {
+        let table = PhraseTable::read(path)?;
+        info!("Merging {} phrases from {} (pass {}).", table.len(), path, tables.len() + 1);
+        tables.push(table);
+    }
+    Ok(tables)
+}
+
+/// Options for `tokenizer()`, which also reads the `PHRASES` options
+pub const TOKENIZER: &'static str =
+    "--subsample=[T] 'randomly discard frequent words, like word2vec (try 1e-5). Needs counts in the wordlist'
+    --stopwords=[FILE] 'file containing words to discard, one per line'
+    --seed=[N] 'seed for random choices, so runs are repeatable'
+    --unknowns=[POLICY] 'what to do with words not in the wordlist: keep (default) as word 0, drop before forming windows, or skip counting them while still taking up room'
+    --subwords=[MERGES] 'split words into subwords with these BPE merges (from cb-bpe). The wordlist must be its subword list'";
+
+/// Create a tokenizer for a vocabulary, as configured by the `TOKENIZER` options
+pub fn tokenizer(args: &ArgMatches, vocab: Vocab) -> Result<Tokenizer> {
+    let mut tokenizer = Tokenizer::new(vocab);
+    tokenizer.set_phrases(phrases(args)?);
+    if let Some(path) = args.value_of("subwords") {
+        let merges = Merges::read(path)?;
+        info!("Splitting words into subwords with {} merges.", merges.len());
+        tokenizer.set_subwords(SubwordEncoder::new(merges));
+    }
+    if let Some(path) = args.value_of("stopwords") {
+        let stopwords = Vocab::read(path)?;
+        info!("Discarding {} stopwords.", stopwords.len());
+        tokenizer.set_stopwords(stopwords.words());
+    }
+    if let Some(unknowns) = value(args, "unknowns")? {
+        tokenizer.set_unknowns(unknowns);
+    }
+    if let Some(threshold) = value(args, "subsample")? {
+        let seed = value(args, "seed")?.unwrap_or(tokens::DEFAULT_SEED);
+        tokenizer.set_subsampling(threshold, seed)?;
+    }
+    Ok(tokenizer)
+}
+
+/// Options for `window()`
+pub const WINDOW: &'static str =
+    "--radius=[N] 'how many tokens on either side of a word are its context (default 10)'
+    --left-radius=[N] 'how many tokens before a word are its context (default --radius)'
+    --right-radius=[N] 'how many tokens after a word are its context (default --radius)'
+    --weighting=[NAME] 'how much context words count by distance: uniform (default), harmonic, linear or gaussian'
+    --sigma=[S] 'width of the gaussian weighting (default half the larger radius)'
+    --include-self 'count each token as its own context too'
+    --contexts=[MODE] 'tell contexts apart by where they are: plain (default), directional (left or right) or positional (each offset)'
+    --boundary=[UNIT] 'never reach across the end of a document (default), paragraph or sentence'";
+
+/// Create a context window, as configured by the `WINDOW` options
+pub fn window(args: &ArgMatches) -> Result<Window> {
+    let radius = value(args, "radius")?.unwrap_or(10);
+    let mut window = Window::symmetric(radius);
+    window.left = value(args, "left-radius")?.unwrap_or(radius);
+    window.right = value(args, "right-radius")?.unwrap_or(radius);
+    window.include_self = args.is_present("include-self");
+    let sigma = value(args, "sigma")?
+        .unwrap_or(::std::cmp::max(window.left, window.right) as f64 / 2.0);
+    window.weighting = Weighting::from_name(args.value_of("weighting").unwrap_or("uniform"), sigma)?;
+    window.contexts = value(args, "contexts")?.unwrap_or(Contexts::Plain);
+    window.boundary = value(args, "boundary")?.unwrap_or(Boundary::Document);
+    info!("Counting {:?} contexts {} tokens before and {} after each word, weighted {:?}, \
+        within each {:?}.",
+        window.contexts, window.left, window.right, window.weighting, window.boundary);
+    Ok(window)
+}
+
+/// Options for `random_index()`, which also reads `--seed` from the `TOKENIZER` options
+pub const RANDOM_INDEX: &'static str =
+    "--rank=[N] 'how many dimensions each random context vector has (default 1024)'
+    --distribution=[NAME] 'how to draw the context vectors: normal (default), uniform or ternary'
+    --fill=[F] 'fraction of the entries of ternary vectors that are nonzero (default 0.1)'
+    --normalization=[NAME] 'how to normalize the context vectors: none (default), l1, l2 or qr'
+    --permutation=[NAME] 'rotate context vectors by where they are, to keep word order: none (default), direction (left or right) or offset (each distance)'";
+
+/// Configure random indexing, as set by the `RANDOM_INDEX` options
+pub fn random_index(args: &ArgMatches) -> Result<RandomIndex> {
+    let distribution = Distribution::from_name(
+        args.value_of("distribution").unwrap_or("normal"),
+        value(args, "fill")?.unwrap_or(0.1))?;
+    let index = RandomIndex {
+        rank: value(args, "rank")?.unwrap_or(1024),
+        distribution: distribution,
+        normalization: value(args, "normalization")?.unwrap_or(Normalization::None),
+        seed: value(args, "seed")?.unwrap_or(tokens::DEFAULT_SEED),
+        permutation: value(args, "permutation")?.unwrap_or(Permutation::None),
+        hashed: false,
+    };
+    if index.rank == 0 {
+        return Err(Error::Other("Random context vectors need at least one dimension.".to_owned()));
+    }
+    info!("Using {:?} random context vectors of rank {}, normalized {:?} and permuted {:?}, with \
+        seed {}.", index.distribution, index.rank, index.normalization, index.permutation, index.seed);
+    Ok(index)
+}
+
+/// Options for `update()`
+pub const UPDATE: &'static str =
+    "--update 'add to the counts already in the output, instead of starting over'
+    --input-name=[NAME] 'name of the input, kept in the JSON file of the output so that it is never counted twice (needed with --update)'";
+
+/// Name the input in the metadata of new counts, as configured by the `UPDATE` options, and if
+/// they are to be added to the output, read and check its metadata
+pub fn update(args: &ArgMatches, output: &str, meta: &mut MatrixMeta) -> Result<Option<MatrixMeta>> {
+    meta.inputs = args.value_of("input-name").into_iter().map(String::from).collect();
+    if !args.is_present("update") {
+        return Ok(None);
+    }
+    if meta.inputs.is_empty() {
+        return Err(Error::Other("Name the input with --input-name, so that it can't be counted \
+            twice.".to_owned()));
+    }
+    if !Path::new(output).exists() {
+        return Err(Error::Other(format!("There's no {} to add to.", output)));
+    }
+    let existing = MatrixMeta::read_for(output)?;
+    meta.check_update(&existing)?;
+    info!("Adding to the counts of {} inputs already in {}.", existing.inputs.len(), output);
+    Ok(Some(existing))
+}
+
+/// Parse an option's value, if it was given
+pub fn value<T>(args: &ArgMatches, name: &str) -> Result<Option<T>>
+    where T: FromStr, T::Err: Display {
+    match args.value_of(name) {
+        None => Ok(None),
+        Some(text) => text.parse().map(Some).map_err(|e| Error::Other(
+            format!("Couldn't understand {:?} as the value of --{}: {}", text, name, e)))
+    }
+}
+
+/// Parse every value of an option that can be given more than once, if it was given
+pub fn values<T>(args: &ArgMatches, name: &str) -> Result<Option<Vec<T>>>
+    where T: FromStr, T::Err: Display {
+    match args.values_of(name) {
+        None => Ok(None),
+        Some(texts) => texts.map(|text| text.parse().map_err(|e| Error::Other(
+            format!("Couldn't understand {:?} as a value of --{}: {}", text, name, e))))
+            .collect::<Result<Vec<T>>>().map(Some)
+    }
+}
@@ -0,0 +1,308 @@
+//! Phrase detection: find word pairs that behave like one word, like "new york"
+//!
+//! A phrase table lists pairs of tokens that should be merged into one token (joined by an
+//! underscore, so "new_york") wherever they are adjacent. Tables are learned from bigram counts,
+//! and applying a second table after the first lets phrases grow longer ("new_york_times").
+//!
+//! Phrase table files have one phrase per line: the first token, a tab, the second token, a tab
+//! and the score the phrase was learned with.
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use errors::*;
+use farm::{self, FarmMap, new_farm};
+use sketch::SpaceSaving;
+
+/// What goes between the words of a phrase when they are merged into one token
+pub const JOINER: &'static str = "_";
+
+/// Pairs of tokens to merge
+pub struct PhraseTable {
+    /// First token -> second token -> score
+    pairs: FarmMap<String, FarmMap<String, f64>>,
+    len: usize,
+}
+
+impl PhraseTable {
+    pub fn new() -> PhraseTable {
+        PhraseTable { pairs: new_farm(), len: 0 }
+    }
+
+    /// Add a phrase, replacing its score if it was already there
+    pub fn insert(&mut self, first: &str, second: &str, score: f64) {
+        let seconds = self.pairs.entry(first.to_owned()).or_insert_with(new_farm);
+        if seconds.insert(second.to_owned(), score).is_none() {
+            self.len += 1;
+        }
+    }
+
+    /// The score of a phrase, if it is one
+    pub fn score(&self, first: &str, second: &str) -> Option<f64> {
+        self.pairs.get(first).and_then(|seconds| seconds.get(second)).cloned()
+    }
+
+    /// How many phrases there are
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// A hash of the phrases, which tells whether two tables merge the same pairs (the scores
+    /// don't matter)
+    pub fn fingerprint(&self) -> String {
+        let mut phrases: Vec<String> = self.pairs.iter()
+            .flat_map(|(first, seconds)| seconds.keys().map(move |second| format!("{} {}", first, second)))
+            .collect();
+        phrases.sort();
+        farm::fingerprint(&phrases)
+    }
+
+    /// Read a phrase table, as written by `write()`
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<PhraseTable> {
+        let file = File::open(path.as_ref())
+            .map_err(|e| Error::MissingFile("phrase table", Some(e)))?;
+        let mut table = PhraseTable::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 3 {
+                return Err(Error::Other(format!(
+                    "Expected phrase table lines to be first word, second word and score, \
+                    separated by tabs, but found {:?} in {}.", line, path.as_ref().display())));
+            }
+            table.insert(fields[0], fields[1], fields[2].parse()?);
+        }
+        Ok(table)
+    }
+
+    /// Write the phrase table, best scoring phrases first
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut phrases: Vec<(&str, &str, f64)> = self.pairs.iter()
+            .flat_map(|(first, seconds)| seconds.iter()
+                .map(move |(second, &score)| (first.as_ref(), second.as_ref(), score)))
+            .collect();
+        phrases.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(::std::cmp::Ordering::Equal)
+            .then((a.0, a.1).cmp(&(b.0, b.1))));
+        let mut writer = BufWriter::new(File::create(path)?);
+        for (first, second, score) in phrases {
+            writeln!(writer, "{}\t{}\t{}", first, second, score)?;
+        }
+        Ok(())
+    }
+
+    /// Merge every phrase in a sequence of tokens into one token
+    ///
+    /// This works greedily from left to right, so in "a b c" where both "a b" and "b c" are
+    /// phrases, only "a_b" is merged.
+    pub fn merge<'t>(&self, tokens: Vec<Cow<'t, str>>) -> Vec<Cow<'t, str>> {
+        if self.is_empty() {
+            return tokens;
+        }
+        let mut merged = Vec::with_capacity(tokens.len());
+        let mut tokens = tokens.into_iter().peekable();
+        while let Some(token) = tokens.next() {
+            let is_phrase = match tokens.peek() {
+                Some(next) => self.score(&token, next).is_some(),
+                None => false
+            };
+            if is_phrase {
+                let next = tokens.next().unwrap();
+                merged.push(Cow::Owned(format!("{}{}{}", token, JOINER, next)));
+            } else {
+                merged.push(token);
+            }
+        }
+        merged
+    }
+}
+
+/// How to score a bigram "a b", given the counts of each word, the bigram and all tokens (N)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scoring {
+    /// Mikolov et al. (2013): (count(a b) - min_count) * N / (count(a) * count(b)). Try 100.
+    Word2vec,
+    /// Pointwise mutual information: ln(count(a b) * N / (count(a) * count(b))). Try 5.
+    Pmi,
+    /// PMI divided by -ln(count(a b) / N), so it is between -1 and 1. Try 0.5.
+    Npmi,
+}
+
+impl Scoring {
+    /// Score a bigram
+    pub fn score(&self, a: u64, b: u64, ab: u64, total: u64, min_count: u64) -> f64 {
+        let (a, b, ab, total) = (a as f64, b as f64, ab as f64, total as f64);
+        match *self {
+            Scoring::Word2vec => (ab - min_count as f64) * total / (a * b),
+            Scoring::Pmi => (ab * total / (a * b)).ln(),
+            Scoring::Npmi => (ab * total / (a * b)).ln() / -(ab / total).ln(),
+        }
+    }
+}
+
+impl FromStr for Scoring {
+    type Err = Error;
+    fn from_str(name: &str) -> Result<Scoring> {
+        match name {
+            "word2vec" => Ok(Scoring::Word2vec),
+            "pmi" => Ok(Scoring::Pmi),
+            "npmi" => Ok(Scoring::Npmi),
+            _ => Err(Error::Other(format!(
+                "There's no phrase scoring called {:?}. Try word2vec, pmi or npmi.", name)))
+        }
+    }
+}
+
+/// Counts words and bigrams, in bounded memory, to learn a phrase table from
+///
+/// Counts come from Space-Saving summaries, so in a corpus too big for the memory given, the
+/// counts of rare words and bigrams are overestimates. Frequent ones, which are the ones that
+/// pass a sensible `min_count`, are kept accurately.
+pub struct PhraseLearner {
+    unigrams: SpaceSaving<String>,
+    bigrams: SpaceSaving<String>,
+    /// Reused to build bigram keys without allocating for each one
+    key: String,
+}
+
+impl PhraseLearner {
+    /// Count in about `bytes` of memory, most of which goes to the bigrams
+    pub fn with_memory(bytes: usize) -> PhraseLearner {
+        PhraseLearner {
+            unigrams: SpaceSaving::with_memory(bytes / 4),
+            bigrams: SpaceSaving::with_memory(bytes - bytes / 4),
+            key: String::new(),
+        }
+    }
+
+    /// Count the words and adjacent pairs in one document's tokens
+    pub fn add<S: AsRef<str>>(&mut self, tokens: &[S]) {
+        for (i, token) in tokens.iter().enumerate() {
+            self.unigrams.insert(token.as_ref());
+            if let Some(next) = tokens.get(i + 1) {
+                // Tokens never contain spaces, so this can't be ambiguous
+                self.key.clear();
+                self.key.push_str(token.as_ref());
+                self.key.push(' ');
+                self.key.push_str(next.as_ref());
+                self.bigrams.insert(&self.key[..]);
+            }
+        }
+    }
+
+    /// Keep the bigrams seen at least `min_count` times that score at least `threshold`
+    pub fn learn(&self, scoring: Scoring, min_count: u64, threshold: f64) -> PhraseTable {
+        let total = self.unigrams.total();
+        let mut table = PhraseTable::new();
+        for bigram in self.bigrams.iter().filter(|bigram| bigram.count >= min_count) {
+            let mut words = bigram.item.splitn(2, ' ');
+            let (first, second) = (words.next().unwrap(), words.next().unwrap());
+            // Words could only be missing if the summary evicted them, when they must be rare
+            let a = self.unigrams.get(first).map(|c| c.count).unwrap_or(bigram.count);
+            let b = self.unigrams.get(second).map(|c| c.count).unwrap_or(bigram.count);
+            let score = scoring.score(a, b, bigram.count, total, min_count);
+            if score >= threshold {
+                table.insert(first, second, score);
+            }
+        }
+        table
+    }
+
+    /// How many tokens have been counted
+    pub fn total(&self) -> u64 {
+        self.unigrams.total()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+    use super::*;
+
+    /// A path for a test's file, out of the way of other tests and runs
+    fn scratch(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("cabarrus-phrases-{}-{}", ::std::process::id(), name))
+    }
+
+    fn cows<'t>(text: &'t str) -> Vec<Cow<'t, str>> {
+        text.split(' ').map(Cow::Borrowed).collect()
+    }
+
+    /// 80 tokens: "the" 30 times, the rest 10 times, "new york" and "big dog" always together
+    fn learner() -> PhraseLearner {
+        let mut learner = PhraseLearner::with_memory(1 << 20);
+        let document: Vec<&str> = (0..10)
+            .flat_map(|_| vec!["the", "new", "york", "the", "cat", "the", "big", "dog"])
+            .collect();
+        learner.add(&document);
+        learner
+    }
+
+    #[test]
+    fn scores_by_hand() {
+        // count(a) = 10, count(b) = 10, count(a b) = 10, N = 80
+        assert_eq!(Scoring::Word2vec.score(10, 10, 10, 80, 5), 4.0);
+        assert!((Scoring::Pmi.score(10, 10, 10, 80, 5) - 8f64.ln()).abs() < 1e-12);
+        // Always together, so as high as NPMI goes
+        assert!((Scoring::Npmi.score(10, 10, 10, 80, 5) - 1.0).abs() < 1e-12);
+        assert!("chi2".parse::<Scoring>().is_err());
+    }
+
+    #[test]
+    fn learns_pairs_over_the_threshold() {
+        let learner = learner();
+        assert_eq!(learner.total(), 80);
+        // "new york" and "big dog" score ln(10 * 80 / (10 * 10)) = ln 8, pairs with "the" only
+        // ln(10 * 80 / (10 * 30)) = ln 8/3 or less
+        let table = learner.learn(Scoring::Pmi, 5, 1.5);
+        assert_eq!(table.len(), 2);
+        assert!((table.score("new", "york").unwrap() - 8f64.ln()).abs() < 1e-12);
+        assert!(table.score("big", "dog").is_some());
+        assert_eq!(table.score("the", "new"), None);
+        assert_eq!(learner.learn(Scoring::Pmi, 5, 0.5).len(), 8);
+        assert_eq!(learner.learn(Scoring::Pmi, 5, 2.5).len(), 0);
+        // "dog the" is seen 9 times, the rest 10
+        assert_eq!(learner.learn(Scoring::Pmi, 10, 0.5).len(), 7);
+        assert_eq!(learner.learn(Scoring::Word2vec, 5, 4.0).len(), 2);
+    }
+
+    #[test]
+    fn merges_greedily_left_to_right() {
+        let mut table = PhraseTable::new();
+        table.insert("a", "b", 1.0);
+        table.insert("b", "c", 2.0);
+        assert_eq!(table.merge(cows("a b c")), cows("a_b c"));
+        assert_eq!(table.merge(cows("b c a b")), cows("b_c a_b"));
+        assert_eq!(table.merge(cows("a a b b c")), cows("a a_b b_c"));
+        assert_eq!(table.merge(cows("c b a")), cows("c b a"));
+        // A second table grows phrases longer
+        let mut longer = PhraseTable::new();
+        longer.insert("a_b", "c", 1.0);
+        assert_eq!(longer.merge(table.merge(cows("a b c b c"))), cows("a_b_c b_c"));
+    }
+
+    #[test]
+    fn round_trips() {
+        let table = learner().learn(Scoring::Pmi, 5, 0.5);
+        let path = scratch("table.tsv");
+        table.write(&path).unwrap();
+        let read = PhraseTable::read(&path).unwrap();
+        assert_eq!(read.len(), table.len());
+        assert_eq!(read.fingerprint(), table.fingerprint());
+        assert_eq!(read.score("new", "york"), table.score("new", "york"));
+        // Best first
+        let mut text = String::new();
+        File::open(&path).unwrap().read_to_string(&mut text).unwrap();
+        assert!(text.starts_with("big\tdog\t") && text.lines().count() == 8);
+
+        File::create(&path).unwrap().write_all(b"new york 2.0\n").unwrap();
+        assert!(PhraseTable::read(&path).is_err());
+        ::std::fs::remove_file(&path).unwrap();
+    }
+}
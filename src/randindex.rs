@@ -0,0 +1,534 @@
+//! Random indexing: approximating a cooccurrence matrix by adding up random context vectors
+//!
+//! Each context word gets a random "index vector" of a fixed rank, and each row of the
+//! approximate matrix is the sum of the index vectors of the contexts the word was seen with.
+//! Random vectors in many dimensions are nearly orthogonal, so the counts can be roughly
+//! recovered, while the matrix needs only `rank` columns however big the vocabulary is.
+//!
+//! Index vectors can be drawn from a few distributions:
+//!
+//! - normal: every entry from N(0, 1)
+//! - uniform: every entry from U(-1, 1)
+//! - ternary: most entries 0, and the rest (a fraction `fill`) +1 or -1 with equal chances, like
+//!   the sparse vectors of Kanerva et al. (2000)
+//!
+//! and then normalized:
+//!
+//! - none: left as drawn
+//! - l1: each vector divided by the sum of its absolute values
+//! - l2: each vector scaled to unit length
+//! - qr: orthonormalized by a QR decomposition. With no more words than dimensions the vectors
+//!   become exactly orthogonal unit vectors; with more, only the dimensions can be orthonormal.
+//!
+//! The vectors only depend on the settings here and the number of rows, so the same settings
+//! always give the same vectors.
+//!
+//! Ternary vectors with a small fill (classic random indexing uses about 10 nonzeros in 1000s
+//! of dimensions) can be kept as just their nonzero entries, so that adding one touches only
+//! those, as long as they aren't QR normalized. They are the same vectors either way.
+//!
+//! Plain random indexing forgets word order, since a context adds the same vector wherever it
+//! is. Rotating each context vector by a permutation that depends on where the context is
+//! (Sahlgren et al., 2008) keeps the order, while the rows stay sums of nearly orthogonal
+//! vectors:
+//!
+//! - none: no rotation
+//! - direction: contexts to the left are rotated back by one dimension and those to the right
+//!   forward by one
+//! - offset: each context is rotated by its offset from the word (-2, -1, +1, +2, ...)
+//!
+//! Rotating a row back by an offset and comparing it with the index vectors then finds the
+//! words most likely seen that far away (see `RandomIndex::neighbours()`).
+//!
+//! Counts can be recovered from the accumulated rows by projecting them back onto the index
+//! vectors (see `RandomIndex::recover()`), give or take the noise from the vectors not being
+//! quite orthogonal, which shrinks with the rank.
+//!
+//! Vectors can also be derived from each token's hash instead, so that no table of them is
+//! needed and any token, even one never seen before, has its own. Hashed ternary vectors have
+//! about `fill * rank` nonzeros, which makes them cheap to add. QR normalization needs every
+//! vector at once, so it can't be used that way.
+use std::str::FromStr;
+use farmhash;
+use ndarray::prelude::*;
+use ndarray::DataMut;
+use ndarray_linalg::QR;
+use rand::{Rng, SeedableRng, StdRng, XorShiftRng};
+use rand::distributions::{IndependentSample, Normal, Range};
+use errors::*;
+
+/// How the entries of index vectors are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Distribution {
+    Normal,
+    Uniform,
+    /// Sparse +1/-1 entries, with this fraction of the entries nonzero
+    Ternary(f64),
+}
+
+impl Distribution {
+    /// Look up a distribution by name. Only the ternary distribution uses `fill`.
+    pub fn from_name(name: &str, fill: f64) -> Result<Distribution> {
+        match name {
+            "normal" => Ok(Distribution::Normal),
+            "uniform" => Ok(Distribution::Uniform),
+            "ternary" if fill > 0.0 && fill <= 1.0 => Ok(Distribution::Ternary(fill)),
+            "ternary" => Err(Error::Other(format!(
+                "The fraction of nonzero entries of ternary vectors should be more than 0 and at \
+                most 1, not {}.", fill))),
+            _ => Err(Error::Other(format!(
+                "There's no index vector distribution called {:?}. Try normal, uniform or ternary.",
+                name)))
+        }
+    }
+}
+
+/// How index vectors are normalized after they are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Normalization {
+    None,
+    L1,
+    L2,
+    Qr,
+}
+
+impl FromStr for Normalization {
+    type Err = Error;
+    fn from_str(name: &str) -> Result<Normalization> {
+        match name {
+            "none" => Ok(Normalization::None),
+            "l1" => Ok(Normalization::L1),
+            "l2" => Ok(Normalization::L2),
+            "qr" => Ok(Normalization::Qr),
+            _ => Err(Error::Other(format!(
+                "There's no normalization called {:?}. Try none, l1, l2 or qr.", name)))
+        }
+    }
+}
+
+/// How index vectors are rotated by where their context is, to encode word order
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Permutation {
+    None,
+    Direction,
+    Offset,
+}
+
+impl Permutation {
+    /// How many dimensions to rotate a context `offset` tokens away from its word (negative
+    /// means to the left)
+    pub fn shift(&self, offset: isize) -> isize {
+        match *self {
+            Permutation::None => 0,
+            Permutation::Direction => offset.signum(),
+            Permutation::Offset => offset,
+        }
+    }
+}
+
+impl Default for Permutation {
+    fn default() -> Permutation {
+        Permutation::None
+    }
+}
+
+impl FromStr for Permutation {
+    type Err = Error;
+    fn from_str(name: &str) -> Result<Permutation> {
+        match name {
+            "none" => Ok(Permutation::None),
+            "direction" => Ok(Permutation::Direction),
+            "offset" => Ok(Permutation::Offset),
+            _ => Err(Error::Other(format!(
+                "There's no permutation called {:?}. Try none, direction or offset.", name)))
+        }
+    }
+}
+
+/// Everything that decides the index vectors
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RandomIndex {
+    /// How many dimensions each vector has
+    pub rank: usize,
+    pub distribution: Distribution,
+    pub normalization: Normalization,
+    pub seed: usize,
+    #[serde(default)]
+    pub permutation: Permutation,
+    /// Whether the vectors come from hashing tokens (see `hashed_vector()`) rather than a table
+    #[serde(default)]
+    pub hashed: bool,
+}
+
+impl RandomIndex {
+    /// Where dimension 0 of a context vector ends up, after rotating it for a context `offset`
+    /// tokens away
+    pub fn rotation(&self, offset: isize) -> usize {
+        let rank = self.rank as isize;
+        ((self.permutation.shift(offset) % rank + rank) % rank) as usize
+    }
+
+    /// Find which of `count` index vectors (starting at row `first`) most likely occur `offset`
+    /// tokens away from a word, given its row of the accumulated matrix, best first
+    ///
+    /// The row is rotated back the way contexts that far away were rotated, and compared with each
+    /// index vector by cosine similarity. Without a permutation, every offset looks the same.
+    pub fn neighbours(&self, memory: ArrayView1<f64>, contexts: &IndexVectors, first: usize,
+        count: usize, offset: isize, top: usize) -> Vec<(usize, f64)> {
+        let rotation = self.rotation(offset);
+        let mut unrotated = Array1::zeros(self.rank);
+        for (dimension, &value) in memory.iter().enumerate() {
+            unrotated[(dimension + self.rank - rotation) % self.rank] = value;
+        }
+        let norm = unrotated.dot(&unrotated).sqrt();
+        let mut scores: Vec<(usize, f64)> = (0..count)
+            .map(|i| (i, contexts.cosine(first + i, &unrotated.view(), norm)))
+            .collect();
+        scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(::std::cmp::Ordering::Equal));
+        scores.truncate(top);
+        scores
+    }
+
+    /// Estimate a block of the cooccurrence matrix from rows of the accumulated matrix and the
+    /// index vectors of the contexts wanted, which were `offset` tokens away if they were permuted
+    ///
+    /// The estimates are `rows · contextsᵀ`, each column divided by its vector's squared length.
+    /// Every other context in a row adds noise, since no two vectors are quite orthogonal, and
+    /// the second matrix is the standard deviation of that noise in each estimate: about
+    /// `|row| / (|context| sqrt(rank))`. When QR normalization made all `drawn` vectors exactly
+    /// orthonormal (there were no more than the rank), there's no noise at all.
+    pub fn recover(&self, rows: ArrayView2<f64>, contexts: ArrayView2<f64>, offset: isize,
+        drawn: usize) -> (Array2<f64>, Array2<f64>) {
+        // Rotate the contexts the way they were when they were added
+        let rotation = self.rotation(offset);
+        let mut rotated = Array2::zeros(contexts.dim());
+        for (dimension, column) in contexts.axis_iter(Axis(1)).enumerate() {
+            rotated.column_mut((dimension + rotation) % self.rank).assign(&column);
+        }
+        let squares: Vec<f64> = rotated.outer_iter().map(|context| context.dot(&context)).collect();
+        let mut estimates = rows.dot(&rotated.t());
+        let mut errors = Array2::zeros(estimates.dim());
+        let exact = self.normalization == Normalization::Qr && drawn <= self.rank;
+        for (i, row) in rows.outer_iter().enumerate() {
+            let row_square = row.dot(&row);
+            for (j, &square) in squares.iter().enumerate() {
+                if square > 0.0 {
+                    estimates[[i, j]] /= square;
+                    if !exact {
+                        errors[[i, j]] = (row_square / (square * self.rank as f64)).sqrt();
+                    }
+                } else {
+                    // A context without a vector left no trace
+                    estimates[[i, j]] = 0.0;
+                }
+            }
+        }
+        (estimates, errors)
+    }
+
+    /// Hash every word into its index vector for a block, in order, as `hashed_vector()` would
+    pub fn hashed_vectors(&self, words: &[String], block: usize) -> Result<SparseVectors> {
+        let mut vectors = SparseVectors { starts: vec![0], entries: vec![] };
+        for word in words.iter() {
+            vectors.entries.extend(self.hashed_vector(word, block)?);
+            vectors.starts.push(vectors.entries.len());
+        }
+        Ok(vectors)
+    }
+
+    /// Whether the vectors can be kept sparse: ternary, and not QR normalized
+    pub fn is_sparse(&self) -> bool {
+        match self.distribution {
+            Distribution::Ternary(_) => self.normalization != Normalization::Qr,
+            _ => false
+        }
+    }
+
+    /// Draw `rows` index vectors, sparse if they can be (see `is_sparse()`) and `dense` isn't set
+    pub fn index_vectors(&self, rows: usize, dense: bool) -> Result<IndexVectors> {
+        if self.is_sparse() && !dense {
+            self.sparse_vectors(rows).map(IndexVectors::Sparse)
+        } else {
+            self.vectors(rows).map(IndexVectors::Dense)
+        }
+    }
+
+    /// Draw `rows` sparse index vectors, the same as `vectors()` would, but keeping only their
+    /// nonzero entries
+    pub fn sparse_vectors(&self, rows: usize) -> Result<SparseVectors> {
+        let fill = match self.distribution {
+            Distribution::Ternary(fill) if self.is_sparse() => fill,
+            _ => return Err(Error::Other(format!(
+                "Only ternary index vectors that aren't QR normalized can be sparse, not {:?} \
+                vectors normalized {:?}.", self.distribution, self.normalization)))
+        };
+        // Drawn in the same order as the dense matrix, one entry at a time
+        let mut rng = StdRng::from_seed(&[self.seed]);
+        let uniform = Range::new(-1.0f64, 1.0);
+        let mut vectors = SparseVectors { starts: vec![0], entries: vec![] };
+        for _ in 0..rows {
+            let start = vectors.entries.len();
+            for dimension in 0..self.rank {
+                let x = uniform.ind_sample(&mut rng);
+                if x.abs() < fill {
+                    vectors.entries.push((dimension, x.signum()));
+                }
+            }
+            let norm = match self.normalization {
+                Normalization::L1 => (vectors.entries.len() - start) as f64,
+                Normalization::L2 => ((vectors.entries.len() - start) as f64).sqrt(),
+                _ => 1.0
+            };
+            if norm > 0.0 && norm != 1.0 {
+                for entry in vectors.entries[start..].iter_mut() { entry.1 /= norm; }
+            }
+            vectors.starts.push(vectors.entries.len());
+        }
+        Ok(vectors)
+    }
+
+    /// Draw `rows` index vectors, one per row
+    pub fn vectors(&self, rows: usize) -> Result<Array2<f64>> {
+        let mut rng = StdRng::from_seed(&[self.seed]);
+        let mut vectors = match self.distribution {
+            Distribution::Normal => {
+                let normal = Normal::new(0.0, 1.0);
+                Array2::from_shape_fn((rows, self.rank), |_| normal.ind_sample(&mut rng))
+            },
+            Distribution::Uniform => {
+                let uniform = Range::new(-1.0, 1.0);
+                Array2::from_shape_fn((rows, self.rank), |_| uniform.ind_sample(&mut rng))
+            },
+            Distribution::Ternary(fill) => {
+                // Like thresholding uniform noise: keep the sign of what lands near zero
+                let uniform = Range::new(-1.0f64, 1.0);
+                Array2::from_shape_fn((rows, self.rank), |_| {
+                    let x = uniform.ind_sample(&mut rng);
+                    if x.abs() < fill { x.signum() } else { 0.0 }
+                })
+            }
+        };
+        match self.normalization {
+            Normalization::None => {},
+            Normalization::L1 => for mut row in vectors.outer_iter_mut() {
+                let norm = row.fold(0.0, |sum, x| sum + x.abs());
+                if norm > 0.0 { row /= norm; }
+            },
+            Normalization::L2 => for mut row in vectors.outer_iter_mut() {
+                let norm = row.dot(&row).sqrt();
+                if norm > 0.0 { row /= norm; }
+            },
+            Normalization::Qr => {
+                vectors = if rows <= self.rank {
+                    // The rows can all be orthonormal: they are the columns of Q for the transpose
+                    let (q, _r) = vectors.t().qr()?;
+                    q.slice(s![.., ..rows]).t().to_owned()
+                } else {
+                    // Too many rows, so the best that can be done is orthonormal columns
+                    let (q, _r) = vectors.qr()?;
+                    q.slice(s![.., ..self.rank]).to_owned()
+                };
+            }
+        }
+        Ok(vectors)
+    }
+
+    /// The index vector of a token in a block of contexts, derived from its hash, as (dimension,
+    /// value) pairs of its nonzero entries
+    ///
+    /// Ternary vectors pick `fill * rank` dimensions (rounded, and at least one), and when two
+    /// picks land on the same dimension there are two pairs for it, which should just be added.
+    pub fn hashed_vector(&self, token: &str, block: usize) -> Result<Vec<(usize, f64)>> {
+        let hash = farmhash::hash64_with_seed(token.as_bytes(),
+            (self.seed as u64).wrapping_add(block as u64));
+        // XorShift is cheap to seed, which matters when every token needs its own generator.
+        // Its seed can't be all zeros, so one word is always odd.
+        let mut rng = XorShiftRng::from_seed(
+            [hash as u32, (hash >> 32) as u32, 0x9e37_79b9, block as u32 | 1]);
+        let mut entries: Vec<(usize, f64)> = match self.distribution {
+            Distribution::Normal => {
+                let normal = Normal::new(0.0, 1.0);
+                (0..self.rank).map(|i| (i, normal.ind_sample(&mut rng))).collect()
+            },
+            Distribution::Uniform => {
+                let uniform = Range::new(-1.0, 1.0);
+                (0..self.rank).map(|i| (i, uniform.ind_sample(&mut rng))).collect()
+            },
+            Distribution::Ternary(fill) => {
+                let nonzeros = ::std::cmp::max(1, (fill * self.rank as f64).round() as usize);
+                let dimensions = Range::new(0, self.rank);
+                (0..nonzeros)
+                    .map(|_| (dimensions.ind_sample(&mut rng), if rng.gen() { 1.0 } else { -1.0 }))
+                    .collect()
+            }
+        };
+        let norm = match self.normalization {
+            Normalization::None => 1.0,
+            Normalization::L1 => entries.iter().map(|&(_, x)| x.abs()).sum(),
+            Normalization::L2 => {
+                // Repeated dimensions add up before the length counts
+                let mut dense = vec![0.0; self.rank];
+                for &(i, x) in entries.iter() { dense[i] += x; }
+                dense.iter().map(|x| x * x).sum::<f64>().sqrt()
+            },
+            Normalization::Qr => return Err(Error::Other(
+                "Hashed index vectors can't be QR normalized, since that needs every vector at \
+                once. Try l2 instead.".to_owned()))
+        };
+        if norm > 0.0 && norm != 1.0 {
+            for entry in entries.iter_mut() { entry.1 /= norm; }
+        }
+        Ok(entries)
+    }
+}
+
+/// Index vectors kept as only their nonzero entries
+pub struct SparseVectors {
+    /// Where each row starts in `entries`, plus where the last one ends
+    starts: Vec<usize>,
+    /// (dimension, value) of every nonzero entry, row by row
+    entries: Vec<(usize, f64)>,
+}
+
+impl SparseVectors {
+    /// How many vectors there are
+    pub fn rows(&self) -> usize {
+        self.starts.len() - 1
+    }
+
+    /// How many nonzero entries there are in all
+    pub fn nnz(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The nonzero entries of a vector, as (dimension, value)
+    pub fn row(&self, row: usize) -> &[(usize, f64)] {
+        &self.entries[self.starts[row]..self.starts[row + 1]]
+    }
+}
+
+/// Index vectors, as a dense matrix or as sparse rows
+pub enum IndexVectors {
+    Dense(Array2<f64>),
+    Sparse(SparseVectors),
+}
+
+impl IndexVectors {
+    /// How many vectors there are
+    pub fn rows(&self) -> usize {
+        match *self {
+            IndexVectors::Dense(ref vectors) => vectors.rows(),
+            IndexVectors::Sparse(ref vectors) => vectors.rows(),
+        }
+    }
+
+    /// Add `weight` times a vector to `target`, rotated so dimension 0 lands on `rotation` (see
+    /// `RandomIndex::rotation()`), which is where nearly all the time of random indexing goes
+    pub fn scaled_add_to<S: DataMut<Elem=f64>>(&self, target: &mut ArrayBase<S, Ix1>, weight: f64,
+        row: usize, rotation: usize) {
+        match *self {
+            IndexVectors::Dense(ref vectors) => {
+                let row = vectors.row(row);
+                let split = row.len() - rotation;
+                target.slice_mut(s![rotation..]).scaled_add(weight, &row.slice(s![..split]));
+                target.slice_mut(s![..rotation]).scaled_add(weight, &row.slice(s![split..]));
+            },
+            IndexVectors::Sparse(ref vectors) => {
+                let rank = target.len();
+                for &(dimension, value) in vectors.row(row) {
+                    target[(dimension + rotation) % rank] += weight * value;
+                }
+            }
+        }
+    }
+
+    /// The cosine similarity of a vector and `other`, whose length is `norm`
+    fn cosine(&self, row: usize, other: &ArrayView1<f64>, norm: f64) -> f64 {
+        let (dot, squares) = match *self {
+            IndexVectors::Dense(ref vectors) => {
+                let row = vectors.row(row);
+                (row.dot(other), row.dot(&row))
+            },
+            // Hashed vectors can have a dimension twice, which makes this only nearly their length
+            IndexVectors::Sparse(ref vectors) => vectors.row(row).iter().fold((0.0, 0.0),
+                |(dot, squares), &(dimension, value)| (dot + value * other[dimension], squares + value * value)),
+        };
+        let length = squares.sqrt();
+        if norm > 0.0 && length > 0.0 { dot / (norm * length) } else { 0.0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ternary(normalization: Normalization, permutation: Permutation) -> RandomIndex {
+        RandomIndex {
+            rank: 64,
+            distribution: Distribution::Ternary(0.1),
+            normalization: normalization,
+            seed: 7,
+            permutation: permutation,
+            hashed: false,
+        }
+    }
+
+    /// Add up contexts the way cb-approx-cooccur does: (row, weight, context, offset) at a time
+    fn accumulate(index: &RandomIndex, contexts: &IndexVectors, adds: &[(usize, f64, usize, isize)])
+        -> Array2<f64> {
+        let mut rows = Array2::zeros((contexts.rows(), index.rank));
+        for &(row, weight, context, offset) in adds {
+            contexts.scaled_add_to(&mut rows.row_mut(row), weight, context, index.rotation(offset));
+        }
+        rows
+    }
+
+    #[test]
+    fn sparse_vectors_are_the_dense_ones() {
+        for &normalization in [Normalization::None, Normalization::L1, Normalization::L2].iter() {
+            let index = ternary(normalization, Permutation::None);
+            let dense = index.vectors(20).unwrap();
+            let sparse = index.sparse_vectors(20).unwrap();
+            assert_eq!(sparse.rows(), 20);
+            for (i, row) in dense.outer_iter().enumerate() {
+                let nonzeros: Vec<(usize, f64)> = row.indexed_iter()
+                    .filter(|&(_, &x)| x != 0.0)
+                    .map(|(dimension, &x)| (dimension, x))
+                    .collect();
+                assert_eq!(sparse.row(i), &nonzeros[..]);
+            }
+        }
+    }
+
+    #[test]
+    fn sparse_accumulation_matches_dense() {
+        let adds: Vec<(usize, f64, usize, isize)> = (0..500)
+            .map(|i| ((i * 7) % 20, 1.0 / (1 + i % 3) as f64, (i * 13) % 20, (i % 5) as isize - 2))
+            .collect();
+        for &normalization in [Normalization::None, Normalization::L1, Normalization::L2].iter() {
+            for &permutation in [Permutation::None, Permutation::Offset].iter() {
+                let index = ternary(normalization, permutation);
+                let dense = index.index_vectors(20, true).unwrap();
+                let sparse = index.index_vectors(20, false).unwrap();
+                match (&dense, &sparse) {
+                    (&IndexVectors::Dense(_), &IndexVectors::Sparse(_)) => {},
+                    _ => panic!("Ternary vectors should be sparse unless they're asked to be dense."),
+                }
+                let dense_rows = accumulate(&index, &dense, &adds);
+                let sparse_rows = accumulate(&index, &sparse, &adds);
+                assert!(dense_rows.iter().any(|&x| x != 0.0));
+                for (d, s) in dense_rows.iter().zip(sparse_rows.iter()) {
+                    assert!((d - s).abs() < 1e-12, "{:?}, {:?}: {} != {}",
+                        normalization, permutation, d, s);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn qr_normalized_vectors_stay_dense() {
+        let index = ternary(Normalization::Qr, Permutation::None);
+        assert!(!index.is_sparse());
+        assert!(index.sparse_vectors(4).is_err());
+    }
+}
@@ -0,0 +1,693 @@
+//! Streaming counters that fit in a fixed amount of memory
+//!
+//! On a full crawl the number of distinct tokens never levels off, because of the long tail of
+//! misspellings, markup debris and numbers. These counters give up exactness on that tail in
+//! exchange for a memory footprint chosen up front.
+use std::borrow::Borrow;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::mem;
+use std::path::Path;
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use farmhash;
+use errors::*;
+use farm::{FarmMap, FarmHashLie, new_farm};
+use sparse::Accumulator;
+
+/// The first bytes of a count-min sketch file
+const CMS_MAGIC: &'static [u8; 8] = b"CBCMS\x01\x00\x00";
+
+/// Rough allowance for the heap-allocated part of an item, like the text of a short string
+const ITEM_ALLOWANCE: usize = 16;
+
+/// One monitored item in a Space-Saving summary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counter<K> {
+    /// The item being counted
+    pub item: K,
+    /// Estimated count, which is never less than the true count
+    pub count: u64,
+    /// The most the count could be overestimated by
+    pub error: u64,
+}
+
+impl<K> Counter<K> {
+    /// A count the item is guaranteed to have reached
+    pub fn lower_bound(&self) -> u64 {
+        self.count - self.error
+    }
+}
+
+/// Space-Saving heavy hitter counter (Metwally, Agrawal and El Abbadi, 2005)
+///
+/// This monitors at most `capacity` items. When a new item arrives and every slot is taken, the
+/// item with the smallest count is evicted and the newcomer inherits its count as error. After
+/// `N` items have been inserted:
+///
+/// - every count overestimates the true count by at most its `error`, which is at most
+///   `N / capacity` (and at most `max_error()`)
+/// - every item with a true count above `max_error()` is monitored, so no frequent item is lost
+/// - the counts of all monitored items sum to exactly `N`
+///
+/// So to keep every word seen at least `f` times, the capacity should be comfortably above `N / f`.
+pub struct SpaceSaving<K: Hash + Eq> {
+    capacity: usize,
+    /// Min-heap by count, so the next item to evict is always at the root
+    heap: Vec<Counter<K>>,
+    /// Where each monitored item is in the heap
+    positions: FarmMap<K, usize>,
+    total: u64,
+}
+
+impl<K: Hash + Eq + Clone> SpaceSaving<K> {
+    /// Monitor at most `capacity` items at once
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "A Space-Saving summary needs room for at least one item.");
+        SpaceSaving {
+            capacity: capacity,
+            heap: Vec::with_capacity(capacity),
+            positions: new_farm(),
+            total: 0,
+        }
+    }
+
+    /// Monitor as many items as will fit in about `bytes` of memory
+    pub fn with_memory(bytes: usize) -> Self {
+        Self::new(::std::cmp::max(1, bytes / Self::bytes_per_item()))
+    }
+
+    /// A rough estimate of the memory each monitored item costs
+    ///
+    /// Each item is stored twice (in the heap and in its index), along with its counts, its
+    /// position and its hash.
+    pub fn bytes_per_item() -> usize {
+        2 * (mem::size_of::<K>() + ITEM_ALLOWANCE)
+            + mem::size_of::<Counter<()>>()
+            + 2 * mem::size_of::<u64>()
+    }
+
+    /// The most items that will be monitored at once
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// How many items are monitored now
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// How many items have been inserted, including repeats
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// The largest overestimate any count could have, so far
+    ///
+    /// Until the summary fills up every count is exact.
+    pub fn max_error(&self) -> u64 {
+        if self.heap.len() < self.capacity {
+            0
+        } else {
+            self.heap[0].count
+        }
+    }
+
+    /// Count one occurrence of an item
+    pub fn insert<Q: ?Sized>(&mut self, item: &Q)
+        where K: Borrow<Q>, Q: Hash + Eq + ToOwned<Owned=K> {
+        self.add(item, 1)
+    }
+
+    /// Count `n` occurrences of an item
+    pub fn add<Q: ?Sized>(&mut self, item: &Q, n: u64)
+        where K: Borrow<Q>, Q: Hash + Eq + ToOwned<Owned=K> {
+        self.total += n;
+        let found = self.positions.get(item).cloned();
+        match found {
+            Some(pos) => {
+                // Already monitored: counts only go up, so it can only sink
+                self.heap[pos].count += n;
+                self.sift_down(pos);
+            }
+            None if self.heap.len() < self.capacity => {
+                let pos = self.heap.len();
+                self.heap.push(Counter { item: item.to_owned(), count: n, error: 0 });
+                self.positions.insert(item.to_owned(), pos);
+                self.sift_up(pos);
+            }
+            None => {
+                // Full: the newcomer takes the place (and count) of the smallest
+                let floor = self.heap[0].count;
+                let evicted = mem::replace(&mut self.heap[0],
+                    Counter { item: item.to_owned(), count: floor + n, error: floor });
+                self.positions.remove::<K>(&evicted.item);
+                self.positions.insert(item.to_owned(), 0);
+                self.sift_down(0);
+            }
+        }
+    }
+
+    /// Look up an item, if it is monitored
+    pub fn get<Q: ?Sized>(&self, item: &Q) -> Option<&Counter<K>>
+        where K: Borrow<Q>, Q: Hash + Eq {
+        self.positions.get(item).map(|&pos| &self.heap[pos])
+    }
+
+    /// All the monitored items, in no particular order
+    pub fn iter(&self) -> ::std::slice::Iter<Counter<K>> {
+        self.heap.iter()
+    }
+
+    /// All the monitored items, most frequent first
+    pub fn into_sorted_vec(self) -> Vec<Counter<K>> {
+        let mut counters = self.heap;
+        counters.sort_by(|a, b| b.count.cmp(&a.count));
+        counters
+    }
+
+    fn sift_up(&mut self, mut pos: usize) {
+        while pos > 0 {
+            let parent = (pos - 1) / 2;
+            if self.heap[parent].count <= self.heap[pos].count {
+                break;
+            }
+            self.swap(parent, pos);
+            pos = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut pos: usize) {
+        loop {
+            let left = 2 * pos + 1;
+            let right = left + 1;
+            let mut smallest = pos;
+            if left < self.heap.len() && self.heap[left].count < self.heap[smallest].count {
+                smallest = left;
+            }
+            if right < self.heap.len() && self.heap[right].count < self.heap[smallest].count {
+                smallest = right;
+            }
+            if smallest == pos {
+                break;
+            }
+            self.swap(pos, smallest);
+            pos = smallest;
+        }
+    }
+
+    /// Swap two heap entries, keeping the index in sync
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        *self.positions.get_mut(&self.heap[a].item).unwrap() = a;
+        *self.positions.get_mut(&self.heap[b].item).unwrap() = b;
+    }
+}
+
+/// HyperLogLog distinct counter (Flajolet, Fusy, Gandouet and Meunier, 2007)
+///
+/// Estimates how many distinct items were inserted using `2^precision` bytes, with a relative
+/// standard error of about `1.04 / sqrt(2^precision)` (0.8% at the default precision of 14).
+/// Counters with the same precision can be merged, which gives the distinct count of everything
+/// inserted into either one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    /// Count with `2^precision` registers, where precision is between 4 and 16
+    pub fn new(precision: u8) -> HyperLogLog {
+        assert!(precision >= 4 && precision <= 16,
+            "HyperLogLog precision must be between 4 and 16, not {}.", precision);
+        HyperLogLog { precision: precision, registers: vec![0; 1 << precision] }
+    }
+
+    /// Count an item
+    pub fn insert<H: Hash + ?Sized>(&mut self, item: &H) {
+        let mut hasher = FarmHashLie::default();
+        item.hash(&mut hasher);
+        self.insert_hash(hasher.finish());
+    }
+
+    /// Count an item by its (well mixed) 64 bit hash
+    pub fn insert_hash(&mut self, hash: u64) {
+        let register = (hash >> (64 - self.precision)) as usize;
+        // Position of the first 1 bit in what's left, counting from 1
+        let rest = hash << self.precision;
+        let rank = ::std::cmp::min(rest.leading_zeros() as u8, 64 - self.precision) + 1;
+        if rank > self.registers[register] {
+            self.registers[register] = rank;
+        }
+    }
+
+    /// Estimate how many distinct items have been inserted
+    pub fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| (-(r as f64)).exp2()).sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if raw <= 2.5 * m && zeros > 0 {
+            // Few items: linear counting is more accurate
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        }
+    }
+
+    /// Add everything counted by another counter of the same precision
+    pub fn merge(&mut self, other: &HyperLogLog) -> Result<()> {
+        if self.precision != other.precision {
+            return Err(Error::InvalidDimensions(format!(
+                "Can't merge HyperLogLog counters of precision {} and {}.",
+                self.precision, other.precision)));
+        }
+        for (mine, &theirs) in self.registers.iter_mut().zip(other.registers.iter()) {
+            if theirs > *mine {
+                *mine = theirs;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Count-min sketch of the entries of a matrix (Cormode and Muthukrishnan, 2005)
+///
+/// Every (row, column) pair is hashed into one cell of each of `depth` rows of `width` cells,
+/// and adding to the pair adds to all of them. Pairs that share a cell inflate each other's
+/// counts, so the smallest of a pair's cells is the best estimate (for nonnegative values):
+///
+/// - it's never less than the true count
+/// - it's more than `error_bound()` (e / width of the total) too high with a probability of at
+///   most e^-depth
+///
+/// The count-mean-min estimate (Deng and Rafiei, 2007) subtracts the noise each cell is expected
+/// to have, which is much closer for the many pairs that are rare, but can be too low.
+///
+/// Memory is `width * depth` counts however many distinct pairs there are, and sketches of the
+/// same shape and seed can be merged, which gives the sketch of everything added to either.
+/// Pairs can't be listed, but the heaviest contexts of a row can be found by asking about every
+/// column (see `top_contexts()`).
+#[derive(Debug, Clone)]
+pub struct CountMinSketch {
+    rows: usize,
+    cols: usize,
+    width: usize,
+    depth: usize,
+    seed: u64,
+    /// The sum of everything added
+    total: f64,
+    /// `depth` rows of `width` cells
+    table: Vec<f64>,
+}
+
+impl CountMinSketch {
+    /// A sketch of a `rows` x `cols` matrix, with `depth` rows of `width` cells
+    pub fn new(rows: usize, cols: usize, width: usize, depth: usize, seed: u64) -> CountMinSketch {
+        assert!(width > 1 && depth > 0, "A count-min sketch needs at least 2x1 cells.");
+        CountMinSketch {
+            rows: rows,
+            cols: cols,
+            width: width,
+            depth: depth,
+            seed: seed,
+            total: 0.0,
+            table: vec![0.0; width * depth],
+        }
+    }
+
+    /// A sketch with `depth` rows as wide as fit in about `bytes` of memory
+    pub fn with_memory(rows: usize, cols: usize, bytes: usize, depth: usize, seed: u64)
+        -> CountMinSketch {
+        let width = bytes / (depth * mem::size_of::<f64>());
+        CountMinSketch::new(rows, cols, ::std::cmp::max(2, width), depth, seed)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The sum of everything added
+    pub fn total(&self) -> f64 {
+        self.total
+    }
+
+    /// How much `estimate()` can be too high, except with a probability of at most e^-depth
+    pub fn error_bound(&self) -> f64 {
+        ::std::f64::consts::E / self.width as f64 * self.total
+    }
+
+    /// Hash a pair into where its cells start and how far apart they are, for double hashing
+    fn hash(&self, row: usize, col: usize) -> (u64, u64) {
+        let mut key = [0; 8];
+        LittleEndian::write_u64(&mut key, (row as u64) << 32 | col as u64);
+        let hash = farmhash::hash64_with_seed(&key, self.seed);
+        (hash & 0xffff_ffff, hash >> 32 | 1)
+    }
+
+    /// The cell of a hashed pair in row `d` of the table
+    fn cell(&self, (first, step): (u64, u64), d: usize) -> usize {
+        d * self.width + (first.wrapping_add(d as u64 * step) % self.width as u64) as usize
+    }
+
+    /// The value in each of a pair's cells
+    fn counts(&self, row: usize, col: usize) -> Vec<f64> {
+        let hash = self.hash(row, col);
+        (0..self.depth).map(|d| self.table[self.cell(hash, d)]).collect()
+    }
+
+    /// The count-min estimate of an entry, which is never too low
+    pub fn estimate(&self, row: usize, col: usize) -> f64 {
+        let hash = self.hash(row, col);
+        (0..self.depth).map(|d| self.table[self.cell(hash, d)]).fold(::std::f64::INFINITY, f64::min)
+    }
+
+    /// The count-mean-min estimate of an entry: the median of each cell less the noise expected
+    /// in it, but never more than `estimate()` or less than 0
+    pub fn estimate_mean_min(&self, row: usize, col: usize) -> f64 {
+        let counts = self.counts(row, col);
+        let min = counts.iter().cloned().fold(::std::f64::INFINITY, f64::min);
+        let mut debiased: Vec<f64> = counts.into_iter()
+            .map(|count| count - (self.total - count) / (self.width - 1) as f64)
+            .collect();
+        debiased.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+        let middle = debiased.len() / 2;
+        let median = if debiased.len() % 2 == 0 {
+            (debiased[middle - 1] + debiased[middle]) / 2.0
+        } else {
+            debiased[middle]
+        };
+        median.min(min).max(0.0)
+    }
+
+    /// The `k` columns of a row with the largest estimates, largest first, among `columns`
+    pub fn top_contexts<I>(&self, row: usize, columns: I, k: usize) -> Vec<(usize, f64)>
+        where I: IntoIterator<Item=usize> {
+        let mut estimates: Vec<(usize, f64)> = columns.into_iter()
+            .map(|col| (col, self.estimate(row, col)))
+            .filter(|&(_, estimate)| estimate > 0.0)
+            .collect();
+        estimates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(::std::cmp::Ordering::Equal)
+            .then_with(|| a.0.cmp(&b.0)));
+        estimates.truncate(k);
+        estimates
+    }
+
+    /// Add everything counted by another sketch of the same shape and seed
+    pub fn merge(&mut self, other: &CountMinSketch) -> Result<()> {
+        if (self.rows, self.cols, self.width, self.depth, self.seed)
+            != (other.rows, other.cols, other.width, other.depth, other.seed) {
+            return Err(Error::InvalidDimensions(format!(
+                "Can't merge a {}x{} count-min sketch of {}x{} cells (seed {}) with a {}x{} one of \
+                {}x{} cells (seed {}).", self.rows, self.cols, self.depth, self.width, self.seed,
+                other.rows, other.cols, other.depth, other.width, other.seed)));
+        }
+        for (mine, &theirs) in self.table.iter_mut().zip(other.table.iter()) {
+            *mine += theirs;
+        }
+        self.total += other.total;
+        Ok(())
+    }
+
+    /// Write the sketch to a file: a magic number, then the shape, width, depth and seed as u64s,
+    /// the total as an f64, and the table of f64s, all little endian
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(CMS_MAGIC)?;
+        for &field in [self.rows, self.cols, self.width, self.depth].iter() {
+            writer.write_u64::<LittleEndian>(field as u64)?;
+        }
+        writer.write_u64::<LittleEndian>(self.seed)?;
+        writer.write_f64::<LittleEndian>(self.total)?;
+        for &cell in self.table.iter() {
+            writer.write_f64::<LittleEndian>(cell)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Read a sketch written by `write()`
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<CountMinSketch> {
+        let file = File::open(path).map_err(|e| Error::MissingFile("count-min sketch", Some(e)))?;
+        let mut reader = BufReader::new(file);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != CMS_MAGIC {
+            return Err(Error::Other("This isn't a count-min sketch from cabarrus.".to_owned()));
+        }
+        let rows = reader.read_u64::<LittleEndian>()? as usize;
+        let cols = reader.read_u64::<LittleEndian>()? as usize;
+        let width = reader.read_u64::<LittleEndian>()? as usize;
+        let depth = reader.read_u64::<LittleEndian>()? as usize;
+        let seed = reader.read_u64::<LittleEndian>()?;
+        let mut sketch = CountMinSketch::new(rows, cols, width, depth, seed);
+        sketch.total = reader.read_f64::<LittleEndian>()?;
+        for cell in sketch.table.iter_mut() {
+            *cell = reader.read_f64::<LittleEndian>()?;
+        }
+        Ok(sketch)
+    }
+}
+
+impl Accumulator for CountMinSketch {
+    fn add(&mut self, row: usize, col: usize, value: f64) {
+        debug_assert!(row < self.rows && col < self.cols,
+            "Entry ({}, {}) is outside a {}x{} matrix.", row, col, self.rows, self.cols);
+        let hash = self.hash(row, col);
+        for d in 0..self.depth {
+            let cell = self.cell(hash, d);
+            self.table[cell] += value;
+        }
+        self.total += value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env;
+    use std::path::PathBuf;
+    use rand::{Rng, SeedableRng, StdRng};
+    use super::*;
+
+    /// A path for a test's file, out of the way of other tests and runs
+    fn scratch(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("cabarrus-sketch-{}-{}", ::std::process::id(), name))
+    }
+
+    /// Word n (from 1 to 200) about 2000 / n times, shuffled
+    fn zipfian_stream() -> Vec<String> {
+        let mut stream = vec![];
+        for n in 1..201 {
+            for _ in 0..2000 / n {
+                stream.push(format!("w{}", n));
+            }
+        }
+        StdRng::from_seed(&[7]).shuffle(&mut stream);
+        stream
+    }
+
+    fn true_counts(stream: &[String]) -> HashMap<&str, u64> {
+        let mut counts = HashMap::new();
+        for word in stream {
+            *counts.entry(&word[..]).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn space_saving_counts_are_bounded() {
+        let stream = zipfian_stream();
+        let truth = true_counts(&stream);
+        let mut summary = SpaceSaving::new(40);
+        for word in stream.iter() {
+            summary.insert(&word[..]);
+        }
+        assert_eq!(summary.len(), 40);
+        assert_eq!(summary.total(), stream.len() as u64);
+        assert!(summary.max_error() > 0);
+        assert!(summary.max_error() <= summary.total() / 40);
+        assert_eq!(summary.iter().map(|counter| counter.count).sum::<u64>(), summary.total());
+        for counter in summary.iter() {
+            let count = truth[&counter.item[..]];
+            assert!(counter.lower_bound() <= count && count <= counter.count,
+                "{} was seen {} times, but counted {} with error {}.",
+                counter.item, count, counter.count, counter.error);
+            assert!(counter.error <= summary.max_error());
+        }
+    }
+
+    #[test]
+    fn space_saving_keeps_heavy_hitters() {
+        let stream = zipfian_stream();
+        let truth = true_counts(&stream);
+        let mut summary = SpaceSaving::new(40);
+        for word in stream.iter() {
+            summary.insert(&word[..]);
+        }
+        // Far more words than slots were evicted along the way, but none of the frequent ones
+        let threshold = summary.max_error();
+        let heavy: Vec<&str> = truth.iter()
+            .filter(|&(_, &count)| count > threshold)
+            .map(|(&word, _)| word)
+            .collect();
+        assert!(heavy.len() >= 10 && heavy.len() < 40);
+        for word in heavy {
+            assert!(summary.get(word).is_some(), "{} was dropped.", word);
+        }
+        // And the most frequent come out on top, in order
+        let sorted = summary.into_sorted_vec();
+        let top: Vec<&str> = sorted.iter().take(3).map(|counter| &counter.item[..]).collect();
+        assert_eq!(top, vec!["w1", "w2", "w3"]);
+    }
+
+    #[test]
+    fn space_saving_is_exact_until_full() {
+        let mut summary = SpaceSaving::new(10);
+        summary.add("a", 5);
+        summary.insert("b");
+        summary.add("a", 2);
+        assert_eq!(summary.max_error(), 0);
+        assert_eq!(summary.get("a"), Some(&Counter { item: "a".to_owned(), count: 7, error: 0 }));
+        assert_eq!(summary.get("b").map(|counter| counter.count), Some(1));
+        assert_eq!(summary.get("c"), None);
+    }
+
+    #[test]
+    fn hyperloglog_estimates_a_known_cardinality() {
+        for &precision in [10, 14].iter() {
+            let mut counter = HyperLogLog::new(precision);
+            for i in 0..100000 {
+                // Twice each, which mustn't matter
+                counter.insert(&format!("token{}", i));
+                counter.insert(&format!("token{}", i));
+            }
+            // Four standard errors
+            let tolerance = 4.0 * 1.04 / ((1 << precision) as f64).sqrt();
+            let error = (counter.estimate() - 100000.0).abs() / 100000.0;
+            assert!(error < tolerance, "Precision {} is {:.4} off.", precision, error);
+        }
+    }
+
+    #[test]
+    fn hyperloglog_counts_small_sets_closely() {
+        let mut counter = HyperLogLog::new(14);
+        assert_eq!(counter.estimate(), 0.0);
+        for i in 0..100 {
+            counter.insert(&i);
+        }
+        assert!((counter.estimate() - 100.0).abs() < 2.0);
+    }
+
+    #[test]
+    fn hyperloglog_merge_is_the_union() {
+        let mut left = HyperLogLog::new(12);
+        let mut right = HyperLogLog::new(12);
+        let mut union = HyperLogLog::new(12);
+        // Overlapping halves: 0..30000 and 20000..50000
+        for i in 0..30000 {
+            left.insert(&format!("token{}", i));
+            union.insert(&format!("token{}", i));
+        }
+        for i in 20000..50000 {
+            right.insert(&format!("token{}", i));
+            union.insert(&format!("token{}", i));
+        }
+        left.merge(&right).unwrap();
+        assert_eq!(left.registers, union.registers);
+        assert_eq!(left.estimate(), union.estimate());
+        assert!(HyperLogLog::new(12).merge(&HyperLogLog::new(14)).is_err());
+    }
+
+    /// Skewed (row, column, count) entries of a 50x50 matrix, many of them repeated
+    fn skewed_entries(seed: usize) -> Vec<(usize, usize, f64)> {
+        let mut rng = StdRng::from_seed(&[seed]);
+        (0..4000).map(|_| {
+            let row = rng.gen_range(0, 50);
+            let col = rng.gen_range(0, 50 - row % 25);
+            (row, col, rng.gen_range(1, 4) as f64)
+        }).collect()
+    }
+
+    #[test]
+    fn count_min_never_undercounts() {
+        let mut truth = HashMap::new();
+        let mut sketch = CountMinSketch::new(50, 50, 256, 4, 11);
+        for (row, col, value) in skewed_entries(3) {
+            *truth.entry((row, col)).or_insert(0.0) += value;
+            sketch.add(row, col, value);
+        }
+        let total: f64 = truth.values().sum();
+        assert_eq!(sketch.total(), total);
+        let mut too_high = 0;
+        for row in 0..50 {
+            for col in 0..50 {
+                let count = truth.get(&(row, col)).cloned().unwrap_or(0.0);
+                let estimate = sketch.estimate(row, col);
+                assert!(estimate >= count, "({}, {}) is {}, but estimated {}.", row, col, count,
+                    estimate);
+                assert!(sketch.estimate_mean_min(row, col) <= estimate);
+                if estimate - count > sketch.error_bound() {
+                    too_high += 1;
+                }
+            }
+        }
+        // Each estimate is off by more than the bound with a chance of at most e^-4, under 2%
+        assert!(too_high < 2500 / 50, "{} estimates are past the bound.", too_high);
+    }
+
+    #[test]
+    fn count_min_merge_is_one_sketch_of_everything() {
+        let mut left = CountMinSketch::new(50, 50, 128, 3, 5);
+        let mut right = CountMinSketch::new(50, 50, 128, 3, 5);
+        let mut whole = CountMinSketch::new(50, 50, 128, 3, 5);
+        for (row, col, value) in skewed_entries(1) {
+            left.add(row, col, value);
+            whole.add(row, col, value);
+        }
+        for (row, col, value) in skewed_entries(2) {
+            right.add(row, col, value);
+            whole.add(row, col, value);
+        }
+        left.merge(&right).unwrap();
+        assert_eq!(left.table, whole.table);
+        assert_eq!(left.total(), whole.total());
+        assert_eq!(left.top_contexts(0, 0..50, 5), whole.top_contexts(0, 0..50, 5));
+        // Another seed hashes pairs to other cells
+        assert!(left.merge(&CountMinSketch::new(50, 50, 128, 3, 6)).is_err());
+        assert!(left.merge(&CountMinSketch::new(50, 50, 64, 3, 5)).is_err());
+    }
+
+    #[test]
+    fn count_min_round_trips() {
+        let mut sketch = CountMinSketch::new(50, 50, 64, 2, 9);
+        for (row, col, value) in skewed_entries(4) {
+            sketch.add(row, col, value);
+        }
+        let path = scratch("round.cms");
+        sketch.write(&path).unwrap();
+        let read = CountMinSketch::read(&path).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+        assert_eq!((read.rows(), read.cols(), read.width(), read.depth()), (50, 50, 64, 2));
+        assert_eq!(read.total(), sketch.total());
+        assert_eq!(read.table, sketch.table);
+    }
+}
@@ -0,0 +1,494 @@
+//! Sparse matrices, which only keep the entries that aren't zero
+//!
+//! A dense cooccurrence matrix needs memory for every pair of words, which for a few hundred
+//! thousand words is terabytes, but most pairs never occur. A `SparseMatrix` needs memory only for
+//! the pairs that do.
+//!
+//! Sparse matrices are written as COO (coordinate) files, all little endian: the magic bytes
+//! `CBCOO\x01\0\0`, then the number of rows, columns and entries as u64s, then every entry as a u32
+//! row, a u32 column and an f64 value, sorted by row and then column.
+//!
+//! When even the pairs that occur don't fit in memory, a `SpillingMatrix` writes them out in
+//! sorted runs whenever it reaches its memory limit, and merges the runs at the end, like GloVe's
+//! `cooccur`.
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use ndarray::prelude::*;
+use ndarray as nd;
+use errors::*;
+use farm::{FarmMap, new_farm};
+use numpy;
+
+/// The start of every COO file, including the format version
+const MAGIC: &'static [u8; 8] = b"CBCOO\x01\x00\x00";
+/// Where the number of entries is in the header
+const NNZ_OFFSET: u64 = 24;
+
+/// Something cooccurrences can be added up in
+pub trait Accumulator {
+    /// Add `value` to the entry at `row` and `col`
+    fn add(&mut self, row: usize, col: usize, value: f64);
+
+    /// Called between documents, when it's a good time to do something slow
+    fn checkpoint(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: nd::DataMut<Elem=f64>> Accumulator for ArrayBase<S, Ix2> {
+    fn add(&mut self, row: usize, col: usize, value: f64) {
+        self[[row, col]] += value;
+    }
+}
+
+/// A matrix of mostly zeros, keeping only the entries that have been added to
+pub struct SparseMatrix {
+    rows: usize,
+    cols: usize,
+    /// (row << 32 | col) -> value
+    entries: FarmMap<u64, f64>,
+}
+
+impl SparseMatrix {
+    /// An empty (all zero) matrix. Rows and columns are limited to 2^32 each.
+    pub fn new(rows: usize, cols: usize) -> SparseMatrix {
+        assert!(rows as u64 <= 1 << 32 && cols as u64 <= 1 << 32,
+            "Sparse matrices can have at most 2^32 rows and columns, not {}x{}.", rows, cols);
+        SparseMatrix { rows: rows, cols: cols, entries: new_farm() }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// How many entries are stored (the number of nonzeros, unless some added up to zero)
+    pub fn nnz(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The value of an entry, which is zero if it was never added to
+    pub fn get(&self, row: usize, col: usize) -> f64 {
+        self.entries.get(&key(row, col)).cloned().unwrap_or(0.0)
+    }
+
+    /// A rough estimate of the memory each entry costs: its key, value and hash, with room for
+    /// the hash table to stay partly empty
+    pub fn bytes_per_entry() -> usize {
+        2 * (mem::size_of::<u64>() + mem::size_of::<f64>() + mem::size_of::<u64>())
+    }
+
+    /// Every stored entry as (row, column, value), sorted by row and then column
+    pub fn sorted_entries(&self) -> Vec<(u32, u32, f64)> {
+        let mut entries: Vec<(u32, u32, f64)> = self.entries.iter()
+            .map(|(&k, &value)| ((k >> 32) as u32, k as u32, value))
+            .collect();
+        entries.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+        entries
+    }
+
+    /// Copy into a dense matrix. Be careful if it's large.
+    pub fn to_dense(&self) -> Array2<f64> {
+        let mut dense = Array2::zeros((self.rows, self.cols));
+        for (&k, &value) in self.entries.iter() {
+            dense[[(k >> 32) as usize, k as u32 as usize]] = value;
+        }
+        dense
+    }
+
+    /// Write the matrix as a COO file
+    pub fn write_coo<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = CooWriter::create(path, self.rows, self.cols)?;
+        for (row, col, value) in self.sorted_entries() {
+            writer.push(row, col, value)?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Read a COO file into memory
+    pub fn read_coo<P: AsRef<Path>>(path: P) -> Result<SparseMatrix> {
+        let reader = CooReader::open(path)?;
+        let mut matrix = SparseMatrix::new(reader.rows(), reader.cols());
+        for entry in reader {
+            let (row, col, value) = entry?;
+            matrix.add(row as usize, col as usize, value);
+        }
+        Ok(matrix)
+    }
+}
+
+impl Accumulator for SparseMatrix {
+    fn add(&mut self, row: usize, col: usize, value: f64) {
+        debug_assert!(row < self.rows && col < self.cols,
+            "Entry ({}, {}) is outside a {}x{} matrix.", row, col, self.rows, self.cols);
+        *self.entries.entry(key(row, col)).or_insert(0.0) += value;
+    }
+}
+
+/// Whether a file is a sparse COO matrix (as opposed to, say, a numpy file)
+pub fn is_coo<P: AsRef<Path>>(path: P) -> Result<bool> {
+    let file = File::open(path.as_ref())
+        .map_err(|e| Error::MissingFile("matrix", Some(e)))?;
+    let mut magic = vec![];
+    file.take(MAGIC.len() as u64).read_to_end(&mut magic)?;
+    Ok(&magic[..] == &MAGIC[..])
+}
+
+/// The shape of a dense (numpy) or sparse (COO) matrix file, without reading the entries
+pub fn shape<P: AsRef<Path>>(path: P) -> Result<(usize, usize)> {
+    if is_coo(path.as_ref())? {
+        let reader = CooReader::open(path)?;
+        Ok((reader.rows(), reader.cols()))
+    } else {
+        let ref matfile = numpy::open_matrix_mmap_readonly(path)?;
+        Ok(numpy::view_matrix_mmap(matfile)?.dim())
+    }
+}
+
+/// Call `entry(row, col, value)` for every nonzero entry of a dense (numpy) or sparse (COO) matrix
+/// file, streaming through it rather than reading it into memory
+pub fn for_each_entry<P, F>(path: P, mut entry: F) -> Result<()>
+    where P: AsRef<Path>, F: FnMut(usize, usize, f64) {
+    if is_coo(path.as_ref())? {
+        for triple in CooReader::open(path)? {
+            let (row, col, value) = triple?;
+            entry(row as usize, col as usize, value);
+        }
+    } else {
+        let ref matfile = numpy::open_matrix_mmap_readonly(path)?;
+        for ((row, col), &value) in numpy::view_matrix_mmap(matfile)?.indexed_iter() {
+            if value != 0.0 {
+                entry(row, col, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Pack a row and column into one key
+fn key(row: usize, col: usize) -> u64 {
+    (row as u64) << 32 | col as u64
+}
+
+/// Writes a COO file one entry at a time, so the entries never all need to be in memory
+///
+/// Entries must be pushed sorted by row and then column. The number of entries in the header is
+/// filled in by `finish()`.
+pub struct CooWriter {
+    writer: BufWriter<File>,
+    nnz: u64,
+}
+
+impl CooWriter {
+    /// Start a COO file for a matrix of this shape
+    pub fn create<P: AsRef<Path>>(path: P, rows: usize, cols: usize) -> Result<CooWriter> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_u64::<LittleEndian>(rows as u64)?;
+        writer.write_u64::<LittleEndian>(cols as u64)?;
+        writer.write_u64::<LittleEndian>(0)?; // entries, filled in at the end
+        Ok(CooWriter { writer: writer, nnz: 0 })
+    }
+
+    /// Write the next entry
+    pub fn push(&mut self, row: u32, col: u32, value: f64) -> Result<()> {
+        self.writer.write_u32::<LittleEndian>(row)?;
+        self.writer.write_u32::<LittleEndian>(col)?;
+        self.writer.write_f64::<LittleEndian>(value)?;
+        self.nnz += 1;
+        Ok(())
+    }
+
+    /// Fill in the header and close the file, returning how many entries were written
+    pub fn finish(mut self) -> Result<u64> {
+        self.writer.seek(SeekFrom::Start(NNZ_OFFSET))?;
+        self.writer.write_u64::<LittleEndian>(self.nnz)?;
+        self.writer.flush()?;
+        Ok(self.nnz)
+    }
+}
+
+/// Reads a COO file one entry at a time, as (row, column, value)
+pub struct CooReader {
+    reader: BufReader<File>,
+    rows: usize,
+    cols: usize,
+    nnz: u64,
+    remaining: u64,
+}
+
+impl CooReader {
+    /// Open a COO file and read its header
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<CooReader> {
+        let file = File::open(path.as_ref())
+            .map_err(|e| Error::MissingFile("sparse matrix", Some(e)))?;
+        let mut reader = BufReader::new(file);
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::Other(format!(
+                "Expected {} to be a sparse COO matrix, but it starts with {:?} instead of {:?}.",
+                path.as_ref().display(), magic, MAGIC)));
+        }
+        let rows = reader.read_u64::<LittleEndian>()? as usize;
+        let cols = reader.read_u64::<LittleEndian>()? as usize;
+        let nnz = reader.read_u64::<LittleEndian>()?;
+        Ok(CooReader { reader: reader, rows: rows, cols: cols, nnz: nnz, remaining: nnz })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// How many entries the file has
+    pub fn nnz(&self) -> u64 {
+        self.nnz
+    }
+
+    /// Read the next entry
+    fn read_entry(&mut self) -> Result<(u32, u32, f64)> {
+        let row = self.reader.read_u32::<LittleEndian>()?;
+        let col = self.reader.read_u32::<LittleEndian>()?;
+        let value = self.reader.read_f64::<LittleEndian>()?;
+        Ok((row, col, value))
+    }
+}
+
+impl Iterator for CooReader {
+    type Item = Result<(u32, u32, f64)>;
+    fn next(&mut self) -> Option<Result<(u32, u32, f64)>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        Some(self.read_entry())
+    }
+}
+
+/// A sparse matrix that spills to disk when it reaches a memory limit
+///
+/// Each spill is a sorted run, written as a COO file next to the output, and `finish()` merges
+/// the runs into the output, adding up the entries they have in common, and deletes them.
+pub struct SpillingMatrix {
+    current: SparseMatrix,
+    /// How many entries to keep in memory before spilling
+    limit: usize,
+    /// The output, which the runs are named after
+    output: PathBuf,
+    runs: Vec<PathBuf>,
+    /// Why spilling failed while adding, to report at the next checkpoint
+    failure: Option<Error>,
+}
+
+impl SpillingMatrix {
+    /// A matrix for `output` using about `bytes` of memory
+    pub fn new<P: AsRef<Path>>(rows: usize, cols: usize, bytes: usize, output: P) -> SpillingMatrix {
+        SpillingMatrix {
+            current: SparseMatrix::new(rows, cols),
+            limit: ::std::cmp::max(1, bytes / SparseMatrix::bytes_per_entry()),
+            output: output.as_ref().to_owned(),
+            runs: vec![],
+            failure: None,
+        }
+    }
+
+    /// How many runs have been spilled so far
+    pub fn runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Write what's in memory as a run and start over
+    fn spill(&mut self) -> Result<()> {
+        let mut path = self.output.as_os_str().to_owned();
+        path.push(format!(".run{}", self.runs.len()));
+        let path = PathBuf::from(path);
+        info!("Spilling {} entries to {}.", self.current.nnz(), path.display());
+        self.current.write_coo(&path)?;
+        self.current.entries.clear();
+        self.runs.push(path);
+        Ok(())
+    }
+
+    /// Write the whole matrix to the output, returning how many entries it has
+    pub fn finish(mut self) -> Result<u64> {
+        if let Some(failure) = self.failure.take() {
+            return Err(failure);
+        }
+        if self.runs.is_empty() {
+            // Everything fit in memory after all
+            self.current.write_coo(&self.output)?;
+            return Ok(self.current.nnz() as u64);
+        }
+        if self.current.nnz() > 0 {
+            self.spill()?;
+        }
+        info!("Merging {} runs into {}.", self.runs.len(), self.output.display());
+        let nnz = merge_runs(&self.runs, &self.output)?;
+        for run in self.runs.iter() {
+            fs::remove_file(run)?;
+        }
+        Ok(nnz)
+    }
+}
+
+impl Accumulator for SpillingMatrix {
+    /// Add to an entry, spilling as soon as the limit is reached, even in the middle of a
+    /// document. Adding can't fail, so if spilling does, the error comes from the next
+    /// `checkpoint()` (or `finish()`), and nothing more is spilled until then.
+    fn add(&mut self, row: usize, col: usize, value: f64) {
+        self.current.add(row, col, value);
+        if self.current.nnz() >= self.limit && self.failure.is_none() {
+            if let Err(failure) = self.spill() {
+                self.failure = Some(failure);
+            }
+        }
+    }
+
+    fn checkpoint(&mut self) -> Result<()> {
+        match self.failure.take() {
+            Some(failure) => Err(failure),
+            None => Ok(())
+        }
+    }
+}
+
+/// Merge sorted COO files of the same shape into one, adding up entries they have in common
+///
+/// Only the next entry of each file is in memory at once. Returns how many entries the merged
+/// file has.
+pub fn merge_runs<P: AsRef<Path>, Q: AsRef<Path>>(runs: &[P], output: Q) -> Result<u64> {
+    let mut readers = vec![];
+    for run in runs {
+        readers.push(CooReader::open(run)?);
+    }
+    let (rows, cols) = match readers.first() {
+        Some(reader) => (reader.rows(), reader.cols()),
+        None => return Err(Error::Other("There are no sparse matrices to merge.".to_owned()))
+    };
+    if readers.iter().any(|reader| (reader.rows(), reader.cols()) != (rows, cols)) {
+        return Err(Error::InvalidDimensions(format!(
+            "All the sparse matrices to merge should be {}x{}.", rows, cols)));
+    }
+
+    // Min-heap of the next entry of each run, by position and then run
+    let mut values = vec![0.0; readers.len()];
+    let mut heap = BinaryHeap::new();
+    for (i, reader) in readers.iter_mut().enumerate() {
+        if let Some(entry) = reader.next() {
+            let (row, col, value) = entry?;
+            values[i] = value;
+            heap.push(Reverse((row, col, i)));
+        }
+    }
+
+    let mut writer = CooWriter::create(output, rows, cols)?;
+    let mut pending: Option<(u32, u32, f64)> = None;
+    while let Some(Reverse((row, col, i))) = heap.pop() {
+        pending = match pending {
+            Some((r, c, total)) if (r, c) == (row, col) => Some((r, c, total + values[i])),
+            Some((r, c, total)) => {
+                writer.push(r, c, total)?;
+                Some((row, col, values[i]))
+            },
+            None => Some((row, col, values[i]))
+        };
+        if let Some(entry) = readers[i].next() {
+            let (row, col, value) = entry?;
+            values[i] = value;
+            heap.push(Reverse((row, col, i)));
+        }
+    }
+    if let Some((r, c, total)) = pending {
+        writer.push(r, c, total)?;
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use super::*;
+
+    /// A path for a test's file, out of the way of other tests and runs
+    fn scratch(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("cabarrus-sparse-{}-{}", ::std::process::id(), name))
+    }
+
+    fn sparse(rows: usize, cols: usize, entries: &[(usize, usize, f64)]) -> SparseMatrix {
+        let mut matrix = SparseMatrix::new(rows, cols);
+        for &(row, col, value) in entries {
+            matrix.add(row, col, value);
+        }
+        matrix
+    }
+
+    #[test]
+    fn merge_runs_adds_up_shared_entries() {
+        let runs = [scratch("merge.run0"), scratch("merge.run1"), scratch("merge.run2")];
+        let output = scratch("merge.coo");
+        sparse(3, 4, &[(0, 0, 1.0), (1, 2, 2.0), (2, 3, 3.0)]).write_coo(&runs[0]).unwrap();
+        sparse(3, 4, &[(1, 2, 5.0), (0, 1, 1.5)]).write_coo(&runs[1]).unwrap();
+        sparse(3, 4, &[]).write_coo(&runs[2]).unwrap();
+
+        assert_eq!(merge_runs(&runs, &output).unwrap(), 4);
+        let entries: Vec<(u32, u32, f64)> = CooReader::open(&output).unwrap()
+            .map(|entry| entry.unwrap())
+            .collect();
+        assert_eq!(entries, vec![(0, 0, 1.0), (0, 1, 1.5), (1, 2, 7.0), (2, 3, 3.0)]);
+        assert_eq!(shape(&output).unwrap(), (3, 4));
+        for path in runs.iter().chain(Some(&output)) {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn merge_runs_needs_matching_shapes() {
+        let runs = [scratch("shapes.run0"), scratch("shapes.run1")];
+        let output = scratch("shapes.coo");
+        sparse(3, 4, &[(0, 0, 1.0)]).write_coo(&runs[0]).unwrap();
+        sparse(4, 3, &[(0, 0, 1.0)]).write_coo(&runs[1]).unwrap();
+        assert!(merge_runs(&runs, &output).is_err());
+        let none: &[PathBuf] = &[];
+        assert!(merge_runs(none, &output).is_err());
+        for path in runs.iter() {
+            fs::remove_file(path).unwrap();
+        }
+        let _ = fs::remove_file(&output);
+    }
+
+    #[test]
+    fn spilling_matches_memory() {
+        let entries: Vec<(usize, usize, f64)> = (0..200)
+            .map(|i| ((i * 7) % 13, (i * 11) % 17, i as f64))
+            .collect();
+        let expected = sparse(13, 17, &entries);
+
+        // A limit of a few entries, so it spills many times within one "document"
+        let output = scratch("spill.coo");
+        let mut spilling = SpillingMatrix::new(13, 17, 5 * SparseMatrix::bytes_per_entry(), &output);
+        for &(row, col, value) in entries.iter() {
+            spilling.add(row, col, value);
+            assert!(spilling.current.nnz() < 5);
+        }
+        spilling.checkpoint().unwrap();
+        assert!(spilling.runs() > 10);
+        assert_eq!(spilling.finish().unwrap(), expected.nnz() as u64);
+
+        let merged = SparseMatrix::read_coo(&output).unwrap();
+        assert_eq!(merged.sorted_entries(), expected.sorted_entries());
+        fs::remove_file(&output).unwrap();
+    }
+}
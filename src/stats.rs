@@ -0,0 +1,320 @@
+//! Corpus statistics, collected in one pass and mergeable across chunks
+//!
+//! Everything in a report can be combined with the report of another chunk of the same corpus,
+//! so parallel jobs can each describe their own chunk and be merged afterwards. The number of
+//! distinct types comes from a HyperLogLog sketch, so it is an estimate (within about 1%), but it
+//! merges exactly as if the chunks had been counted together.
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use serde_json;
+use errors::*;
+use farm::{FarmMap, new_farm};
+use sketch::{HyperLogLog, SpaceSaving};
+
+/// Precision of the type counter, which uses 2^14 bytes and is off by about 0.8%
+const TYPE_PRECISION: u8 = 14;
+/// Track this many more words than will be reported, so the reported counts are accurate
+const TOP_SLACK: usize = 16;
+/// Record a point of the Heaps' law growth curve each time the token count grows by this factor
+const HEAPS_GROWTH: f64 = 1.25;
+/// The first point of the Heaps' law growth curve
+const HEAPS_START: u64 = 1000;
+
+/// A straight line fit by least squares, in log-log space
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PowerLaw {
+    /// Multiplier: y = coefficient * x ^ exponent
+    pub coefficient: f64,
+    pub exponent: f64,
+}
+
+impl PowerLaw {
+    /// Fit y = c * x^e to points with positive coordinates (any others are ignored)
+    pub fn fit(points: &[(f64, f64)]) -> Option<PowerLaw> {
+        let logs: Vec<(f64, f64)> = points.iter()
+            .filter(|&&(x, y)| x > 0.0 && y > 0.0)
+            .map(|&(x, y)| (x.ln(), y.ln()))
+            .collect();
+        if logs.len() < 2 {
+            return None;
+        }
+        let n = logs.len() as f64;
+        let mean_x = logs.iter().map(|p| p.0).sum::<f64>() / n;
+        let mean_y = logs.iter().map(|p| p.1).sum::<f64>() / n;
+        let covariance: f64 = logs.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+        let variance: f64 = logs.iter().map(|p| (p.0 - mean_x) * (p.0 - mean_x)).sum();
+        if variance == 0.0 {
+            return None;
+        }
+        let exponent = covariance / variance;
+        Some(PowerLaw { coefficient: (mean_y - exponent * mean_x).exp(), exponent: exponent })
+    }
+}
+
+/// Statistics about a corpus, or a chunk of one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorpusReport {
+    /// How many documents (WARC records)
+    pub documents: u64,
+    /// How many tokens, over all documents
+    pub tokens: u64,
+    /// Estimated number of distinct tokens
+    pub types: f64,
+    /// Types divided by tokens
+    pub type_token_ratio: f64,
+    /// Histogram of document lengths in tokens. Entry 0 counts empty documents, and entry k
+    /// counts documents of at least 2^(k-1) and less than 2^k tokens.
+    pub document_lengths: Vec<u64>,
+    /// Points (tokens, types) of vocabulary growth, as tokens were read in order
+    pub heaps_curve: Vec<(u64, f64)>,
+    /// Heaps' law fit to the growth curve: types = K * tokens ^ beta, where beta is the exponent
+    pub heaps_fit: Option<PowerLaw>,
+    /// The most frequent tokens, with their counts, most frequent first
+    pub top_tokens: Vec<(String, u64)>,
+    /// Zipf's law fit to the top tokens: count = C * rank ^ -s, where -s is the exponent
+    pub zipf_fit: Option<PowerLaw>,
+    /// The distinct counter behind `types`, kept so reports can be merged
+    type_sketch: HyperLogLog,
+}
+
+impl CorpusReport {
+    /// Read a report, as written by `write()`
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<CorpusReport> {
+        let file = File::open(path.as_ref())
+            .map_err(|e| Error::MissingFile("corpus report", Some(e)))?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Write the report as JSON
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    /// Combine this report with that of another chunk of the same corpus
+    ///
+    /// Counts, lengths and types merge exactly. The top tokens are summed, so a token that was
+    /// in the top list of only some chunks is undercounted. The growth curves can't be put end to
+    /// end, since the types two chunks have in common aren't known, so the merged curve is the
+    /// points of both (each chunk is a sample of the same growth) and the overall total.
+    pub fn merge(&mut self, other: &CorpusReport) -> Result<()> {
+        self.type_sketch.merge(&other.type_sketch)?;
+        self.documents += other.documents;
+        self.tokens += other.tokens;
+        self.types = self.type_sketch.estimate();
+        for (k, &count) in other.document_lengths.iter().enumerate() {
+            if k < self.document_lengths.len() {
+                self.document_lengths[k] += count;
+            } else {
+                self.document_lengths.push(count);
+            }
+        }
+
+        self.heaps_curve.extend(other.heaps_curve.iter().cloned());
+        self.heaps_curve.push((self.tokens, self.types));
+        self.heaps_curve.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let keep = ::std::cmp::max(self.top_tokens.len(), other.top_tokens.len());
+        let mut top: FarmMap<String, u64> = new_farm();
+        for &(ref token, count) in self.top_tokens.iter().chain(other.top_tokens.iter()) {
+            *top.entry(token.clone()).or_insert(0) += count;
+        }
+        self.top_tokens = top.into_iter().collect();
+        self.top_tokens.sort_by(|a, b| (b.1, &a.0).cmp(&(a.1, &b.0)));
+        self.top_tokens.truncate(keep);
+        self.fit();
+        Ok(())
+    }
+
+    /// Update the ratio and fits from the counts
+    fn fit(&mut self) {
+        self.type_token_ratio = if self.tokens > 0 { self.types / self.tokens as f64 } else { 0.0 };
+        let heaps: Vec<(f64, f64)> = self.heaps_curve.iter()
+            .map(|&(tokens, types)| (tokens as f64, types))
+            .collect();
+        self.heaps_fit = PowerLaw::fit(&heaps);
+        let zipf: Vec<(f64, f64)> = self.top_tokens.iter()
+            .enumerate()
+            .map(|(rank, &(_, count))| ((rank + 1) as f64, count as f64))
+            .collect();
+        self.zipf_fit = PowerLaw::fit(&zipf);
+    }
+}
+
+/// Collects a `CorpusReport` one document at a time
+pub struct CorpusStats {
+    documents: u64,
+    tokens: u64,
+    document_lengths: Vec<u64>,
+    heaps_curve: Vec<(u64, f64)>,
+    next_checkpoint: u64,
+    types: HyperLogLog,
+    top: SpaceSaving<String>,
+    top_len: usize,
+}
+
+impl CorpusStats {
+    /// Collect statistics, reporting the `top` most frequent tokens
+    pub fn new(top: usize) -> CorpusStats {
+        CorpusStats {
+            documents: 0,
+            tokens: 0,
+            document_lengths: vec![],
+            heaps_curve: vec![],
+            next_checkpoint: HEAPS_START,
+            types: HyperLogLog::new(TYPE_PRECISION),
+            top: SpaceSaving::new(::std::cmp::max(1, top * TOP_SLACK)),
+            top_len: top,
+        }
+    }
+
+    /// Count one document's tokens
+    pub fn add<S: AsRef<str>>(&mut self, tokens: &[S]) {
+        self.documents += 1;
+        self.tokens += tokens.len() as u64;
+        for token in tokens {
+            self.types.insert(token.as_ref());
+            self.top.insert(token.as_ref());
+        }
+
+        // Bit length is the histogram bucket: 0 for 0, 1 for 1, 2 for 2-3, 3 for 4-7...
+        let bucket = (64 - (tokens.len() as u64).leading_zeros()) as usize;
+        if self.document_lengths.len() <= bucket {
+            self.document_lengths.resize(bucket + 1, 0);
+        }
+        self.document_lengths[bucket] += 1;
+
+        if self.tokens >= self.next_checkpoint {
+            self.heaps_curve.push((self.tokens, self.types.estimate()));
+            while self.next_checkpoint <= self.tokens {
+                self.next_checkpoint = (self.next_checkpoint as f64 * HEAPS_GROWTH) as u64;
+            }
+        }
+    }
+
+    /// How many tokens have been counted
+    pub fn tokens(&self) -> u64 {
+        self.tokens
+    }
+
+    /// Summarize everything counted so far
+    pub fn report(&self) -> CorpusReport {
+        let types = self.types.estimate();
+        let mut heaps_curve = self.heaps_curve.clone();
+        if heaps_curve.last().map(|point| point.0) != Some(self.tokens) {
+            heaps_curve.push((self.tokens, types));
+        }
+        let mut report = CorpusReport {
+            documents: self.documents,
+            tokens: self.tokens,
+            types: types,
+            type_token_ratio: 0.0,
+            document_lengths: self.document_lengths.clone(),
+            heaps_curve: heaps_curve,
+            heaps_fit: None,
+            top_tokens: self.top.iter()
+                .map(|counter| (counter.item.clone(), counter.count))
+                .collect(),
+            zipf_fit: None,
+            type_sketch: self.types.clone(),
+        };
+        report.top_tokens.sort_by(|a, b| (b.1, &a.0).cmp(&(a.1, &b.0)));
+        report.top_tokens.truncate(self.top_len);
+        report.fit();
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, StdRng};
+    use super::*;
+
+    /// Documents of 100 tokens drawn from a Zipfian distribution over `vocab` words
+    fn zipfian_documents(documents: usize, vocab: usize, seed: usize) -> Vec<Vec<String>> {
+        let mut cumulative = Vec::with_capacity(vocab);
+        let mut total = 0.0;
+        for rank in 1..vocab + 1 {
+            total += 1.0 / rank as f64;
+            cumulative.push(total);
+        }
+        let mut rng = StdRng::from_seed(&[seed]);
+        (0..documents).map(|_| (0..100).map(|_| {
+            let x = rng.gen::<f64>() * total;
+            let rank = match cumulative.binary_search_by(|c| c.partial_cmp(&x).unwrap()) {
+                Ok(i) | Err(i) => i
+            };
+            format!("w{}", rank)
+        }).collect()).collect()
+    }
+
+    #[test]
+    fn power_law_fits_exactly() {
+        let points: Vec<(f64, f64)> = (1..10)
+            .map(|x| (x as f64, 3.0 * (x as f64).powf(0.5)))
+            .collect();
+        let fit = PowerLaw::fit(&points).unwrap();
+        assert!((fit.coefficient - 3.0).abs() < 1e-9 && (fit.exponent - 0.5).abs() < 1e-9);
+        // Points at 0 can't be fit in log space, and one point isn't a line
+        assert!(PowerLaw::fit(&[(0.0, 0.0), (5.0, 2.0)]).is_none());
+    }
+
+    #[test]
+    fn report_counts() {
+        let mut stats = CorpusStats::new(3);
+        stats.add(&["a", "b", "a"]);
+        stats.add::<&str>(&[]);
+        stats.add(&["a", "c", "d", "a", "b"]);
+        let report = stats.report();
+        assert_eq!((report.documents, report.tokens), (3, 8));
+        assert!((report.types - 4.0).abs() < 0.01);
+        // Empty, then 2-3 tokens, then 4-7
+        assert_eq!(report.document_lengths, vec![1, 0, 1, 1]);
+        assert_eq!(report.top_tokens[..2], [("a".to_owned(), 4), ("b".to_owned(), 2)]);
+        assert_eq!(report.top_tokens.len(), 3);
+    }
+
+    #[test]
+    fn merged_chunks_match_one_pass() {
+        let documents = zipfian_documents(1000, 20000, 5);
+        let mut whole = CorpusStats::new(20);
+        let mut first = CorpusStats::new(20);
+        let mut second = CorpusStats::new(20);
+        for (i, document) in documents.iter().enumerate() {
+            whole.add(document);
+            if i < 600 { first.add(document) } else { second.add(document) }
+        }
+        let whole = whole.report();
+        let mut merged = first.report();
+        merged.merge(&second.report()).unwrap();
+
+        // These merge exactly
+        assert_eq!((merged.documents, merged.tokens), (whole.documents, whole.tokens));
+        assert_eq!(merged.document_lengths, whole.document_lengths);
+        assert_eq!(merged.types, whole.types);
+        assert_eq!(merged.type_token_ratio, whole.type_token_ratio);
+        // The top words are frequent enough to be in the top of both chunks, so they add up
+        assert_eq!(merged.top_tokens[..5], whole.top_tokens[..5]);
+
+        // The chunks' curves each start from nothing, so they retrace the early growth of the
+        // whole corpus rather than continuing it, and only the last point reaches the end
+        assert_eq!(merged.heaps_curve.last(), whole.heaps_curve.last());
+        assert!(merged.heaps_curve.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        assert_eq!(merged.heaps_curve.len(),
+            first.report().heaps_curve.len() + second.report().heaps_curve.len() + 1);
+        // But growth is the same wherever it starts, so the fit is close to the one-pass fit
+        let (merged_fit, whole_fit) = (merged.heaps_fit.unwrap(), whole.heaps_fit.unwrap());
+        assert!(whole_fit.exponent > 0.3 && whole_fit.exponent < 1.0);
+        assert!((merged_fit.exponent - whole_fit.exponent).abs() < 0.05,
+            "Merged exponent {} is far from {}.", merged_fit.exponent, whole_fit.exponent);
+    }
+
+    #[test]
+    fn merging_refuses_other_precisions() {
+        let mut report = CorpusStats::new(5).report();
+        let mut other = CorpusStats::new(5).report();
+        other.type_sketch = HyperLogLog::new(10);
+        assert!(report.merge(&other).is_err());
+    }
+}
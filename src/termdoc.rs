@@ -0,0 +1,257 @@
+//! Term-document matrices, for LSA and topic models
+//!
+//! Each document gets the next document id as it's added, and the matrix has a row for every word
+//! (the unknown word first, as usual) and a column for every document. Raw counts are usually
+//! reweighted before use, to play down words that are frequent everywhere:
+//!
+//! - tf: the raw count
+//! - tfidf: count * ln(N / df), where N is the number of documents and df is how many have the word
+//! - log-entropy: ln(1 + count) * (1 + sum over documents of p ln p / ln N), where p is the
+//!   word's count in a document over its count in the whole corpus (Dumais, 1991)
+//! - bm25: Okapi BM25, idf * count * (k1 + 1) / (count + k1 * (1 - b + b * length / mean length)),
+//!   with the idf ln(1 + (N - df + 0.5) / (df + 0.5)), which is never negative
+use std::path::Path;
+use std::str::FromStr;
+use errors::*;
+use sparse::CooWriter;
+
+/// How the entries of a term-document matrix are weighted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TermWeighting {
+    Tf,
+    TfIdf,
+    LogEntropy,
+    /// BM25 with its term frequency saturation (k1, usually 1.2) and length normalization (b,
+    /// usually 0.75)
+    Bm25 { k1: f64, b: f64 },
+}
+
+impl FromStr for TermWeighting {
+    type Err = Error;
+    fn from_str(name: &str) -> Result<TermWeighting> {
+        match name {
+            "tf" => Ok(TermWeighting::Tf),
+            "tfidf" => Ok(TermWeighting::TfIdf),
+            "log-entropy" => Ok(TermWeighting::LogEntropy),
+            "bm25" => Ok(TermWeighting::Bm25 { k1: 1.2, b: 0.75 }),
+            _ => Err(Error::Other(format!(
+                "There's no term weighting called {:?}. Try tf, tfidf, log-entropy or bm25.", name)))
+        }
+    }
+}
+
+/// Counts words in documents, one document at a time
+pub struct TermDocCounter {
+    /// Rows in the matrix: the vocabulary and the unknown word
+    rows: usize,
+    /// (word, document, count), in the order they were counted
+    entries: Vec<(u32, u32, f64)>,
+    /// How many tokens each document has
+    document_lengths: Vec<u64>,
+    /// How many documents have each word
+    document_frequencies: Vec<u64>,
+    /// How many times each word occurs in the whole corpus
+    corpus_frequencies: Vec<u64>,
+}
+
+impl TermDocCounter {
+    /// Count words of a vocabulary of `vocab_size` words (not counting the unknown word)
+    pub fn new(vocab_size: usize) -> TermDocCounter {
+        TermDocCounter {
+            rows: vocab_size + 1,
+            entries: vec![],
+            document_lengths: vec![],
+            document_frequencies: vec![0; vocab_size + 1],
+            corpus_frequencies: vec![0; vocab_size + 1],
+        }
+    }
+
+    /// Count the word ids of a document, returning its document id
+    ///
+    /// With `skip_unknowns`, the unknown word 0 isn't counted, but unknown words still make the
+    /// document longer.
+    pub fn add_document(&mut self, ids: &[usize], skip_unknowns: bool) -> usize {
+        let document = self.document_lengths.len();
+        self.document_lengths.push(ids.len() as u64);
+        let mut sorted: Vec<usize> = ids.iter().cloned().filter(|&id| !skip_unknowns || id != 0).collect();
+        sorted.sort();
+        let mut i = 0;
+        while i < sorted.len() {
+            let id = sorted[i];
+            let count = sorted[i..].iter().take_while(|&&other| other == id).count();
+            self.entries.push((id as u32, document as u32, count as f64));
+            self.document_frequencies[id] += 1;
+            self.corpus_frequencies[id] += count as u64;
+            i += count;
+        }
+        document
+    }
+
+    /// How many documents have been added
+    pub fn documents(&self) -> usize {
+        self.document_lengths.len()
+    }
+
+    /// How many words the matrix has rows for, including the unknown word
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// How many distinct (word, document) pairs have been counted
+    pub fn nnz(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The global weight of each word, which every count of it is multiplied by
+    fn global_weights(&self, weighting: TermWeighting) -> Vec<f64> {
+        let n = self.documents() as f64;
+        match weighting {
+            TermWeighting::Tf => vec![1.0; self.rows],
+            TermWeighting::TfIdf => self.document_frequencies.iter()
+                .map(|&df| if df > 0 { (n / df as f64).ln() } else { 0.0 })
+                .collect(),
+            TermWeighting::Bm25 { .. } => self.document_frequencies.iter()
+                .map(|&df| (1.0 + (n - df as f64 + 0.5) / (df as f64 + 0.5)).ln())
+                .collect(),
+            TermWeighting::LogEntropy => {
+                // 1 + sum of p ln p / ln N, summed over the documents each word is in
+                let mut weights = vec![0.0; self.rows];
+                for &(word, _, count) in self.entries.iter() {
+                    let p = count / self.corpus_frequencies[word as usize] as f64;
+                    weights[word as usize] += p * p.ln();
+                }
+                weights.iter()
+                    .map(|&entropy| if n > 1.0 { 1.0 + entropy / n.ln() } else { 1.0 })
+                    .collect()
+            }
+        }
+    }
+
+    /// Write the weighted matrix as a sparse COO file, returning how many entries it has
+    ///
+    /// Entries that are weighted down to zero are left out.
+    pub fn write_coo<P: AsRef<Path>>(&mut self, path: P, weighting: TermWeighting) -> Result<u64> {
+        let global = self.global_weights(weighting);
+        let mean_length = self.document_lengths.iter().sum::<u64>() as f64
+            / ::std::cmp::max(1, self.documents()) as f64;
+        self.entries.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+
+        let mut writer = CooWriter::create(path, self.rows, self.documents())?;
+        for &(word, document, count) in self.entries.iter() {
+            let local = match weighting {
+                TermWeighting::Tf | TermWeighting::TfIdf => count,
+                TermWeighting::LogEntropy => count.ln_1p(),
+                TermWeighting::Bm25 { k1, b } => {
+                    let length = self.document_lengths[document as usize] as f64;
+                    count * (k1 + 1.0) / (count + k1 * (1.0 - b + b * length / mean_length))
+                }
+            };
+            let value = local * global[word as usize];
+            if value != 0.0 {
+                // Words in every document have no weight under some schemes, so leave them out
+                writer.push(word, document, value)?;
+            }
+        }
+        writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use sparse::SparseMatrix;
+    use super::*;
+
+    /// A path for a test's file, out of the way of other tests and runs
+    fn scratch(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("cabarrus-termdoc-{}-{}", ::std::process::id(), name))
+    }
+
+    /// Two documents over three words: "a a b" and "b c c ?", where ? is an unknown word
+    fn counter() -> TermDocCounter {
+        let mut counter = TermDocCounter::new(3);
+        assert_eq!(counter.add_document(&[1, 2, 1], false), 0);
+        assert_eq!(counter.add_document(&[3, 0, 2, 3], false), 1);
+        counter
+    }
+
+    fn weighted(weighting: TermWeighting, name: &str) -> (u64, SparseMatrix) {
+        let path = scratch(name);
+        let written = counter().write_coo(&path, weighting).unwrap();
+        let matrix = SparseMatrix::read_coo(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        (written, matrix)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-12, "{} isn't {}.", actual, expected);
+    }
+
+    #[test]
+    fn tf_is_the_counts() {
+        let (written, matrix) = weighted(TermWeighting::Tf, "tf.coo");
+        assert_eq!((matrix.rows(), matrix.cols(), written), (4, 2, 5));
+        assert_eq!(matrix.sorted_entries(),
+            vec![(0, 1, 1.0), (1, 0, 2.0), (2, 0, 1.0), (2, 1, 1.0), (3, 1, 2.0)]);
+    }
+
+    #[test]
+    fn tfidf_leaves_out_words_in_every_document() {
+        // b is in both documents, so its idf is ln(2 / 2) = 0; the rest are in one, ln 2
+        let (written, matrix) = weighted(TermWeighting::TfIdf, "tfidf.coo");
+        assert_eq!(written, 3);
+        assert_close(matrix.get(0, 1), 2f64.ln());
+        assert_close(matrix.get(1, 0), 2.0 * 2f64.ln());
+        assert_close(matrix.get(3, 1), 2.0 * 2f64.ln());
+        assert_eq!(matrix.get(2, 0), 0.0);
+    }
+
+    #[test]
+    fn log_entropy_by_hand() {
+        // b is spread evenly, so its entropy is ln 2 and its weight 1 - ln 2 / ln 2 = 0, while
+        // the words in one document have entropy 0 and weight 1
+        let (written, matrix) = weighted(TermWeighting::LogEntropy, "log-entropy.coo");
+        assert_eq!(written, 3);
+        assert_close(matrix.get(0, 1), 2f64.ln());
+        assert_close(matrix.get(1, 0), 3f64.ln());
+        assert_close(matrix.get(3, 1), 3f64.ln());
+    }
+
+    #[test]
+    fn bm25_by_hand() {
+        // The mean length is 3.5; idf is ln(1 + 1.5 / 1.5) = ln 2 for words in one document and
+        // ln(1 + 0.5 / 2.5) = ln 1.2 for b
+        let (written, matrix) = weighted(TermWeighting::Bm25 { k1: 1.2, b: 0.75 }, "bm25.coo");
+        assert_eq!(written, 5);
+        let saturate = |count: f64, length: f64| {
+            count * 2.2 / (count + 1.2 * (0.25 + 0.75 * length / 3.5))
+        };
+        assert_close(matrix.get(1, 0), saturate(2.0, 3.0) * 2f64.ln());
+        assert_close(matrix.get(2, 0), saturate(1.0, 3.0) * 1.2f64.ln());
+        assert_close(matrix.get(0, 1), saturate(1.0, 4.0) * 2f64.ln());
+        assert_close(matrix.get(2, 1), saturate(1.0, 4.0) * 1.2f64.ln());
+        assert_close(matrix.get(3, 1), saturate(2.0, 4.0) * 2f64.ln());
+        // The longer document's single b counts for less than the shorter one's
+        assert!(matrix.get(2, 1) < matrix.get(2, 0));
+    }
+
+    #[test]
+    fn skipped_unknowns_still_count_toward_length() {
+        let mut counter = TermDocCounter::new(3);
+        counter.add_document(&[1, 0, 0], true);
+        counter.add_document(&[1], true);
+        assert_eq!(counter.nnz(), 2);
+        assert_eq!(counter.document_lengths, vec![3, 1]);
+        assert_eq!(counter.document_frequencies, vec![0, 2, 0, 0]);
+    }
+
+    #[test]
+    fn weightings_by_name() {
+        assert_eq!("bm25".parse::<TermWeighting>().unwrap(),
+            TermWeighting::Bm25 { k1: 1.2, b: 0.75 });
+        assert_eq!("log-entropy".parse::<TermWeighting>().unwrap(), TermWeighting::LogEntropy);
+        assert!("idf".parse::<TermWeighting>().is_err());
+    }
+}
@@ -0,0 +1,392 @@
+//! Splitting text into tokens
+//!
+//! Text is split on Unicode word boundaries, and the runs of whitespace between words are left
+//! out, so they are never counted and never take up room in a context window. Every binary splits
+//! text this way. They used to keep whitespace as tokens, which made every other token an unknown
+//! word, so token counts from then are about twice as high, and their windows reached only half as
+//! many words. Counts from before the change aren't comparable with counts from after it.
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str::FromStr;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use unicode_segmentation::{UnicodeSegmentation, UWordBounds};
+use rand::{Rng, SeedableRng, StdRng};
+use bpe::SubwordEncoder;
+use errors::*;
+use farm::{self, FarmMap, new_farm};
+use phrases::PhraseTable;
+use vocab::Vocab;
+
+/// Seed for random choices when the user doesn't pick one
+pub const DEFAULT_SEED: usize = 3141592653589793;
+
+/// Iterator over the words in some text, see `words()`
+pub struct Words<'t> {
+    bounds: UWordBounds<'t>,
+}
+
+/// Split text on Unicode word boundaries, leaving out the whitespace in between
+///
+/// Punctuation is kept, one token per mark. Unlike `split_word_bounds()` on its own, no run of
+/// whitespace (spaces, tabs, newlines, no-break spaces) is ever a token; see the module docs.
+pub fn words(content: &str) -> Words {
+    Words { bounds: content.split_word_bounds() }
+}
+
+impl<'t> Iterator for Words<'t> {
+    type Item = &'t str;
+
+    fn next(&mut self) -> Option<&'t str> {
+        self.bounds.find(|bound| !bound.chars().all(char::is_whitespace))
+    }
+}
+
+/// Iterator over the words in some text with phrases merged, see `phrase_words()`
+pub enum PhraseWords<'t> {
+    /// No phrases to merge, so the words come straight from the text
+    Plain(Words<'t>),
+    /// Words with phrases merged, which needs them all at once
+    Merged(::std::vec::IntoIter<Cow<'t, str>>),
+}
+
+/// Split text into words, then merge phrases, applying each phrase table in turn
+///
+/// Without any tables, this is just `words()`.
+pub fn phrase_words<'t>(content: &'t str, tables: &[PhraseTable]) -> PhraseWords<'t> {
+    if tables.is_empty() {
+        return PhraseWords::Plain(words(content));
+    }
+    let mut tokens: Vec<Cow<str>> = words(content).map(Cow::Borrowed).collect();
+    for table in tables {
+        tokens = table.merge(tokens);
+    }
+    PhraseWords::Merged(tokens.into_iter())
+}
+
+impl<'t> Iterator for PhraseWords<'t> {
+    type Item = Cow<'t, str>;
+
+    fn next(&mut self) -> Option<Cow<'t, str>> {
+        match *self {
+            PhraseWords::Plain(ref mut words) => words.next().map(Cow::Borrowed),
+            PhraseWords::Merged(ref mut tokens) => tokens.next(),
+        }
+    }
+}
+
+/// What to do with words that aren't in the vocabulary
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Unknowns {
+    /// Keep them as the unknown word (id 0), which is counted like any other word
+    Keep,
+    /// Leave them out before windows are formed, so the words around them become closer, like
+    /// word2vec does
+    Drop,
+    /// Keep them as id 0, so they still take up room in windows, but don't count any pairs with
+    /// them. Callers do the skipping, since windows don't know about ids.
+    Skip,
+}
+
+impl FromStr for Unknowns {
+    type Err = Error;
+    fn from_str(name: &str) -> Result<Unknowns> {
+        match name {
+            "keep" => Ok(Unknowns::Keep),
+            "drop" => Ok(Unknowns::Drop),
+            "skip" => Ok(Unknowns::Skip),
+            _ => Err(Error::Other(format!(
+                "There's no way to treat unknown words called {:?}. Try keep, drop or skip.", name)))
+        }
+    }
+}
+
+/// Turns text into word ids, optionally leaving some words out before any windows are formed
+///
+/// Since discarded words are removed from the sequence entirely, the words on either side of them
+/// become closer, which is how word2vec treats them as well.
+pub struct Tokenizer {
+    vocab: Vocab,
+    /// Phrases to merge into single tokens, one pass per table
+    phrases: Vec<PhraseTable>,
+    /// Splits words into subwords, if the vocabulary is of subwords
+    subwords: Option<SubwordEncoder>,
+    /// Words to always leave out, whether they are in the vocabulary or not
+    stopwords: FarmMap<String, ()>,
+    /// Chance of keeping each word id, if subsampling, and the threshold it came from
+    keep: Option<Vec<f64>>,
+    threshold: Option<f64>,
+    rng: StdRng,
+    unknowns: Unknowns,
+    /// How many tokens have been looked up, not counting stopwords, and how many were unknown
+    looked_up: u64,
+    unknown: u64,
+}
+
+/// How a `Tokenizer` was set up, besides its vocabulary, as kept in the JSON file of a matrix
+///
+/// Phrase tables, merges and stopwords are kept as fingerprints, like word lists.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenizerSettings {
+    /// The phrase tables applied, in order
+    pub phrases: Vec<String>,
+    /// The merges words were split into subwords with, if any
+    pub subwords: Option<String>,
+    /// The stopwords left out, if any
+    pub stopwords: Option<String>,
+    /// The subsampling threshold, if frequent words were subsampled
+    pub subsample: Option<f64>,
+    pub unknowns: Unknowns,
+}
+
+impl Tokenizer {
+    /// Look up words in a vocabulary, and otherwise keep every token
+    pub fn new(vocab: Vocab) -> Tokenizer {
+        Tokenizer {
+            vocab: vocab,
+            phrases: vec![],
+            subwords: None,
+            stopwords: new_farm(),
+            keep: None,
+            threshold: None,
+            rng: StdRng::from_seed(&[DEFAULT_SEED]),
+            unknowns: Unknowns::Keep,
+            looked_up: 0,
+            unknown: 0,
+        }
+    }
+
+    /// The vocabulary words are looked up in
+    pub fn vocab(&self) -> &Vocab {
+        &self.vocab
+    }
+
+    /// Merge phrases into single tokens, applying each table in turn
+    ///
+    /// The merged tokens, like "new_york", need to be in the vocabulary to get their own ids.
+    pub fn set_phrases(&mut self, tables: Vec<PhraseTable>) {
+        self.phrases = tables;
+    }
+
+    /// Split every word into subwords before looking them up, for an open vocabulary
+    ///
+    /// The vocabulary needs to be the subwords made by the same merges, as `cb-bpe` writes it.
+    /// Each subword then takes a place of its own in the context windows.
+    pub fn set_subwords(&mut self, encoder: SubwordEncoder) {
+        self.subwords = Some(encoder);
+    }
+
+    /// Always leave out these words
+    pub fn set_stopwords(&mut self, stopwords: &[String]) {
+        self.stopwords = stopwords.iter().map(|w| (w.clone(), ())).collect();
+    }
+
+    /// Randomly leave out frequent words, as in Mikolov et al. (2013)
+    ///
+    /// A word with frequency `f` (its count over the total of the vocabulary's counts) is discarded
+    /// with probability `1 - sqrt(t / f)`, so words rarer than the threshold `t` are always kept.
+    /// Around 1e-5 is typical. The choices are repeatable for the same seed. This needs counts in
+    /// the vocabulary, like `cb-vocab` writes; unknown words are always kept.
+    pub fn set_subsampling(&mut self, threshold: f64, seed: usize) -> Result<()> {
+        let total = match self.vocab.total() {
+            Some(total) if total > 0 => total as f64,
+            _ => return Err(Error::Other(
+                "Subsampling needs word counts, but the word list has none. \
+                Make one with cb-vocab, or leave out subsampling.".to_string()))
+        };
+        let keep = (0..self.vocab.len() + 1)
+            .map(|id| match self.vocab.count(id) {
+                Some(count) if count > 0 => (threshold / (count as f64 / total)).sqrt().min(1.0),
+                _ => 1.0
+            })
+            .collect();
+        self.keep = Some(keep);
+        self.threshold = Some(threshold);
+        self.rng = StdRng::from_seed(&[seed]);
+        Ok(())
+    }
+
+    /// Choose what to do with words that aren't in the vocabulary. They are kept by default.
+    pub fn set_unknowns(&mut self, unknowns: Unknowns) {
+        self.unknowns = unknowns;
+    }
+
+    /// What happens to words that aren't in the vocabulary
+    pub fn unknowns(&self) -> Unknowns {
+        self.unknowns
+    }
+
+    /// Everything but the vocabulary that decides which tokens come out, to record with counts
+    pub fn settings(&self) -> TokenizerSettings {
+        let mut stopwords: Vec<&String> = self.stopwords.keys().collect();
+        stopwords.sort();
+        TokenizerSettings {
+            phrases: self.phrases.iter().map(PhraseTable::fingerprint).collect(),
+            subwords: self.subwords.as_ref().map(SubwordEncoder::fingerprint),
+            stopwords: if stopwords.is_empty() { None } else { Some(farm::fingerprint(&stopwords)) },
+            subsample: self.threshold,
+            unknowns: self.unknowns,
+        }
+    }
+
+    /// How many tokens have been looked up in the vocabulary so far, not counting stopwords
+    pub fn looked_up(&self) -> u64 {
+        self.looked_up
+    }
+
+    /// The fraction of tokens looked up so far that weren't in the vocabulary
+    pub fn unknown_rate(&self) -> f64 {
+        if self.looked_up == 0 { 0.0 } else { self.unknown as f64 / self.looked_up as f64 }
+    }
+
+    /// Tokenize a string according to the vocabulary. Unknowns will be 0, unless they are
+    /// dropped.
+    pub fn tokenize(&mut self, content: &str) -> Vec<usize> {
+        let mut ids = vec![];
+        self.walk(content, |_, id| ids.push(id));
+        ids
+    }
+
+    /// Tokenize a string, keeping the tokens themselves rather than their ids
+    ///
+    /// Everything else happens as in `tokenize()`, so this is for open vocabularies, where any
+    /// token can come out. (Unless unknowns are dropped, which leaves only the known words.)
+    pub fn tokenize_words(&mut self, content: &str) -> Vec<String> {
+        let mut words = vec![];
+        self.walk(content, |word, _| words.push(word.to_owned()));
+        words
+    }
+
+    /// Pass on every token that isn't left out, along with its id
+    fn walk<F: FnMut(&str, usize)>(&mut self, content: &str, mut emit: F) {
+        let vocab = &self.vocab;
+        let keep = &self.keep;
+        let rng = &mut self.rng;
+        let drop_unknowns = self.unknowns == Unknowns::Drop;
+        let looked_up = &mut self.looked_up;
+        let unknown = &mut self.unknown;
+        let mut push = |word: &str| {
+            let id = vocab.id(word);
+            *looked_up += 1;
+            if id == 0 {
+                *unknown += 1;
+                if drop_unknowns { return; }
+            }
+            // Only roll the dice for words that could be discarded when subsampling
+            let kept = match *keep {
+                Some(ref keep) => keep[id] >= 1.0 || rng.next_f64() < keep[id],
+                None => true
+            };
+            if kept { emit(word, id); }
+        };
+        for word in phrase_words(content, &self.phrases) {
+            if self.stopwords.contains_key(&*word) {
+                continue;
+            }
+            match self.subwords {
+                Some(ref mut encoder) => for subword in encoder.encode(&word) {
+                    push(subword);
+                },
+                None => push(&word)
+            }
+        }
+    }
+}
+
+/// Writes tokenized segments to a file, so a corpus can be gone over again without reading and
+/// tokenizing it again
+///
+/// The file starts with the number of segments as a little endian u64, filled in by `finish()`,
+/// and each segment is its length and then its ids, as little endian u32s.
+pub struct TokenCacheWriter {
+    writer: BufWriter<File>,
+    segments: u64,
+}
+
+impl TokenCacheWriter {
+    /// Start a token cache
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<TokenCacheWriter> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_u64::<LittleEndian>(0)?; // segments, filled in at the end
+        Ok(TokenCacheWriter { writer: writer, segments: 0 })
+    }
+
+    /// Add a segment's ids
+    pub fn push(&mut self, ids: &[usize]) -> Result<()> {
+        self.writer.write_u32::<LittleEndian>(ids.len() as u32)?;
+        for &id in ids {
+            self.writer.write_u32::<LittleEndian>(id as u32)?;
+        }
+        self.segments += 1;
+        Ok(())
+    }
+
+    /// Fill in the header and flush, returning how many segments were written
+    pub fn finish(mut self) -> Result<u64> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_u64::<LittleEndian>(self.segments)?;
+        self.writer.flush()?;
+        Ok(self.segments)
+    }
+}
+
+/// Reads the segments of a token cache back, in order
+pub struct TokenCacheReader {
+    reader: BufReader<File>,
+    remaining: u64,
+}
+
+impl TokenCacheReader {
+    /// Open a token cache written by `TokenCacheWriter`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<TokenCacheReader> {
+        let file = File::open(path).map_err(|e| Error::MissingFile("token cache", Some(e)))?;
+        let mut reader = BufReader::new(file);
+        let segments = reader.read_u64::<LittleEndian>()?;
+        Ok(TokenCacheReader { reader: reader, remaining: segments })
+    }
+
+    fn read_segment(&mut self) -> Result<Vec<usize>> {
+        let len = self.reader.read_u32::<LittleEndian>()? as usize;
+        let mut ids = Vec::with_capacity(len);
+        for _ in 0..len {
+            ids.push(self.reader.read_u32::<LittleEndian>()? as usize);
+        }
+        Ok(ids)
+    }
+}
+
+impl Iterator for TokenCacheReader {
+    type Item = Result<Vec<usize>>;
+    fn next(&mut self) -> Option<Result<Vec<usize>>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        Some(self.read_segment())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_leave_out_whitespace() {
+        let text = "The cat\tsat,  on\n\nthe\u{a0}mat.";
+        assert_eq!(words(text).collect::<Vec<_>>(),
+            vec!["The", "cat", "sat", ",", "on", "the", "mat", "."]);
+        // Everything else comes through as Unicode splits it
+        let bounds: Vec<&str> = text.split_word_bounds()
+            .filter(|bound| !bound.trim().is_empty())
+            .collect();
+        assert_eq!(words(text).collect::<Vec<_>>(), bounds);
+        assert_eq!(words(" \n\t ").count(), 0);
+    }
+
+    #[test]
+    fn words_keep_contractions_and_numbers_whole() {
+        assert_eq!(words("don't pay $3.50!").collect::<Vec<_>>(),
+            vec!["don't", "pay", "$", "3.50", "!"]);
+    }
+}
@@ -0,0 +1,301 @@
+//! Reweighting cooccurrence counts before they are factorized
+//!
+//! Association measures compare how often a word and a context occur together with how often
+//! they would by chance, given how often each occurs at all (the marginals):
+//!
+//! - pmi: ln(P(w, c) / (P(w) P(c)))
+//! - ppmi: the same, but negative values become 0
+//!
+//! Both can be shifted down by ln k (Levy and Goldberg, 2014, which matches word2vec with k
+//! negative samples), and the context distribution can be smoothed by raising the context counts
+//! to a power alpha (0.75 in word2vec), which keeps rare contexts from getting huge PMIs. Pairs
+//! that never occur stay 0 rather than becoming -infinity.
+//!
+//! There are also plain rescalings, which don't need the marginals:
+//!
+//! - sqrt: the square root of each count
+//! - log: ln(1 + count)
+//!
+//! Marginals take one pass over the matrix and the transform another, so a matrix only ever needs
+//! to be streamed through, whether it is an mmap or a sparse file (see `transform_file()`).
+//!
+//! Every transform is of counts, so negative entries are refused rather than turned into NaN.
+use std::path::Path;
+use std::str::FromStr;
+use errors::*;
+use numpy;
+use sparse::{self, CooReader, CooWriter};
+
+/// A way to reweight cooccurrence counts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transform {
+    Pmi,
+    Ppmi,
+    Sqrt,
+    Log,
+}
+
+impl Transform {
+    /// Whether the transform needs the row and column sums
+    pub fn needs_marginals(&self) -> bool {
+        match *self {
+            Transform::Pmi | Transform::Ppmi => true,
+            Transform::Sqrt | Transform::Log => false,
+        }
+    }
+}
+
+impl FromStr for Transform {
+    type Err = Error;
+    fn from_str(name: &str) -> Result<Transform> {
+        match name {
+            "pmi" => Ok(Transform::Pmi),
+            "ppmi" => Ok(Transform::Ppmi),
+            "sqrt" => Ok(Transform::Sqrt),
+            "log" => Ok(Transform::Log),
+            _ => Err(Error::Other(format!(
+                "There's no transform called {:?}. Try pmi, ppmi, sqrt or log.", name)))
+        }
+    }
+}
+
+/// Row and column sums of a matrix, added up one entry at a time
+pub struct Marginals {
+    rows: Vec<f64>,
+    cols: Vec<f64>,
+}
+
+impl Marginals {
+    /// Empty sums for a matrix of this shape
+    pub fn new(rows: usize, cols: usize) -> Marginals {
+        Marginals { rows: vec![0.0; rows], cols: vec![0.0; cols] }
+    }
+
+    /// Count an entry
+    pub fn add(&mut self, row: usize, col: usize, value: f64) {
+        self.rows[row] += value;
+        self.cols[col] += value;
+    }
+
+    /// The sum of every entry
+    pub fn total(&self) -> f64 {
+        self.rows.iter().sum()
+    }
+}
+
+/// A transform, ready to apply to entries
+pub struct Reweighting {
+    transform: Transform,
+    /// ln of each row's probability
+    log_rows: Vec<f64>,
+    /// ln of each (smoothed) column's probability
+    log_cols: Vec<f64>,
+    /// ln of the total count
+    log_total: f64,
+    /// ln k
+    shift: f64,
+}
+
+impl Reweighting {
+    /// Prepare a transform that needs no marginals
+    pub fn plain(transform: Transform) -> Reweighting {
+        assert!(!transform.needs_marginals(), "{:?} needs the marginals of the matrix.", transform);
+        Reweighting {
+            transform: transform,
+            log_rows: vec![],
+            log_cols: vec![],
+            log_total: 0.0,
+            shift: 0.0,
+        }
+    }
+
+    /// Prepare a transform using a matrix's marginals, shifted by ln `k` and with the column
+    /// counts raised to `alpha` (1 means no smoothing)
+    pub fn new(transform: Transform, marginals: &Marginals, k: f64, alpha: f64) -> Reweighting {
+        let total = marginals.total();
+        let smoothed: Vec<f64> = marginals.cols.iter().map(|&count| count.powf(alpha)).collect();
+        let smoothed_total: f64 = smoothed.iter().sum();
+        Reweighting {
+            transform: transform,
+            log_rows: marginals.rows.iter().map(|&count| (count / total).ln()).collect(),
+            log_cols: smoothed.iter().map(|&count| (count / smoothed_total).ln()).collect(),
+            log_total: total.ln(),
+            shift: k.ln(),
+        }
+    }
+
+    /// The new value of an entry, which has to be a count
+    pub fn apply(&self, row: usize, col: usize, value: f64) -> Result<f64> {
+        if value < 0.0 {
+            return Err(Error::Other(format!(
+                "Entry ({}, {}) is {}, but {:?} needs counts, which can't be negative.",
+                row, col, value, self.transform)));
+        }
+        if value == 0.0 {
+            return Ok(0.0);
+        }
+        Ok(match self.transform {
+            Transform::Sqrt => value.sqrt(),
+            Transform::Log => value.ln_1p(),
+            Transform::Pmi | Transform::Ppmi => {
+                let pmi = value.ln() - self.log_total - self.log_rows[row] - self.log_cols[col]
+                    - self.shift;
+                if self.transform == Transform::Ppmi && pmi < 0.0 { 0.0 } else { pmi }
+            }
+        })
+    }
+}
+
+/// Reweight the matrix in a file, dense (numpy) or sparse (COO), into another of the same format,
+/// shifting PMI by ln `k` and raising the context counts to `alpha` (see `Reweighting::new()`)
+///
+/// The input is streamed through twice, once to add up the marginals (when they are needed) and
+/// once to transform. Returns the shape of the matrix.
+pub fn transform_file<P, Q>(input: P, output: Q, transform: Transform, k: f64, alpha: f64)
+    -> Result<(usize, usize)> where P: AsRef<Path>, Q: AsRef<Path> {
+    let prepare = |marginals: &Marginals| if transform.needs_marginals() {
+        Reweighting::new(transform, marginals, k, alpha)
+    } else {
+        Reweighting::plain(transform)
+    };
+    if sparse::is_coo(input.as_ref())? {
+        let reader = CooReader::open(input.as_ref())?;
+        let (rows, cols) = (reader.rows(), reader.cols());
+        let mut marginals = Marginals::new(rows, cols);
+        if transform.needs_marginals() {
+            for entry in reader {
+                let (row, col, value) = entry?;
+                marginals.add(row as usize, col as usize, value);
+            }
+        }
+        let reweighting = prepare(&marginals);
+        let mut writer = CooWriter::create(output, rows, cols)?;
+        for entry in CooReader::open(input.as_ref())? {
+            let (row, col, value) = entry?;
+            let value = reweighting.apply(row as usize, col as usize, value)?;
+            if value != 0.0 {
+                writer.push(row, col, value)?;
+            }
+        }
+        let nnz = writer.finish()?;
+        info!("Wrote {} nonzero entries.", nnz);
+        Ok((rows, cols))
+    } else {
+        let ref matfile = numpy::open_matrix_mmap_readonly(input)?;
+        let ref mat = numpy::view_matrix_mmap(matfile)?;
+        let (rows, cols) = mat.dim();
+        let mut marginals = Marginals::new(rows, cols);
+        if transform.needs_marginals() {
+            for ((row, col), &value) in mat.indexed_iter() {
+                marginals.add(row, col, value);
+            }
+        }
+        let reweighting = prepare(&marginals);
+        let outfile = numpy::create_empty_mmap(output, &[rows, cols])?;
+        let mut out = numpy::read_matrix_mmap(&outfile)?;
+        for ((row, col), &value) in mat.indexed_iter() {
+            out[[row, col]] = reweighting.apply(row, col, value)?;
+        }
+        Ok((rows, cols))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use ndarray::prelude::*;
+    use sparse::{Accumulator, SparseMatrix};
+    use super::*;
+
+    /// A path for a test's file, out of the way of other tests and runs
+    fn scratch(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("cabarrus-transform-{}-{}", ::std::process::id(), name))
+    }
+
+    /// Counts with a total of 8, rows adding up to 4 and 4, and columns to 5 and 3
+    fn counts() -> Array2<f64> {
+        arr2(&[[4.0, 0.0], [1.0, 3.0]])
+    }
+
+    fn assert_close(actual: &Array2<f64>, expected: &Array2<f64>) {
+        assert_eq!(actual.dim(), expected.dim());
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() < 1e-12, "got {}, expected {}", actual, expected);
+        }
+    }
+
+    /// Reweight `counts()` both as a dense and as a sparse file, checking both against `expected`
+    fn check(transform: Transform, k: f64, alpha: f64, expected: Array2<f64>) {
+        let name = format!("{:?}-{}-{}", transform, k, alpha);
+        let (dense_in, dense_out) = (scratch(&format!("{}.in.npy", name)), scratch(&format!("{}.npy", name)));
+        numpy::write_matrix(&dense_in, &counts()).unwrap();
+        assert_eq!(transform_file(&dense_in, &dense_out, transform, k, alpha).unwrap(), (2, 2));
+        assert_close(&numpy::read_matrix(&dense_out).unwrap(), &expected);
+
+        let (coo_in, coo_out) = (scratch(&format!("{}.in.coo", name)), scratch(&format!("{}.coo", name)));
+        let mut matrix = SparseMatrix::new(2, 2);
+        for ((row, col), &value) in counts().indexed_iter() {
+            matrix.add(row, col, value);
+        }
+        matrix.write_coo(&coo_in).unwrap();
+        assert_eq!(transform_file(&coo_in, &coo_out, transform, k, alpha).unwrap(), (2, 2));
+        assert_close(&SparseMatrix::read_coo(&coo_out).unwrap().to_dense(), &expected);
+
+        for path in [dense_in, dense_out, coo_in, coo_out].iter() {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn pmi_compares_with_chance() {
+        // P(w, c) / (P(w) P(c)) = count * 8 / (row * column), and unseen pairs stay 0
+        check(Transform::Pmi, 1.0, 1.0, arr2(&[
+            [(4.0 * 8.0 / (4.0 * 5.0) as f64).ln(), 0.0],
+            [(1.0 * 8.0 / (4.0 * 5.0) as f64).ln(), (3.0 * 8.0 / (4.0 * 3.0) as f64).ln()]]));
+    }
+
+    #[test]
+    fn ppmi_drops_negative_pmi() {
+        check(Transform::Ppmi, 1.0, 1.0, arr2(&[
+            [(8.0 / 5.0 as f64).ln(), 0.0],
+            [0.0, (2.0 as f64).ln()]]));
+    }
+
+    #[test]
+    fn shifted_ppmi_subtracts_ln_k() {
+        check(Transform::Ppmi, 1.5, 1.0, arr2(&[
+            [(8.0 / 5.0 / 1.5 as f64).ln(), 0.0],
+            [0.0, (2.0 / 1.5 as f64).ln()]]));
+    }
+
+    #[test]
+    fn alpha_smooths_contexts() {
+        // With alpha 0.5, the columns count as sqrt(5) and sqrt(3) out of their sum
+        let (c0, c1) = ((5.0 as f64).sqrt(), (3.0 as f64).sqrt());
+        let (p0, p1) = (c0 / (c0 + c1), c1 / (c0 + c1));
+        // ln(P(w, c) / P(w)) = ln(count / 4)
+        check(Transform::Pmi, 1.0, 0.5, arr2(&[
+            [(1.0 / p0).ln(), 0.0],
+            [(0.25 / p0).ln(), (0.75 / p1).ln()]]));
+    }
+
+    #[test]
+    fn rescalings_need_no_marginals() {
+        check(Transform::Sqrt, 1.0, 1.0, arr2(&[[2.0, 0.0], [1.0, (3.0 as f64).sqrt()]]));
+        check(Transform::Log, 1.0, 1.0, arr2(&[
+            [(5.0 as f64).ln(), 0.0],
+            [(2.0 as f64).ln(), (4.0 as f64).ln()]]));
+    }
+
+    #[test]
+    fn negative_entries_are_refused() {
+        let mut marginals = Marginals::new(1, 2);
+        marginals.add(0, 0, 2.0);
+        marginals.add(0, 1, 1.0);
+        assert!(Reweighting::new(Transform::Ppmi, &marginals, 1.0, 1.0).apply(0, 0, -1.0).is_err());
+        assert!(Reweighting::plain(Transform::Log).apply(0, 0, -0.5).is_err());
+        assert_eq!(Reweighting::plain(Transform::Sqrt).apply(0, 0, 4.0).unwrap(), 2.0);
+    }
+}
@@ -0,0 +1,143 @@
+//! Word lists, which decide the rows and columns of the matrices
+//!
+//! A word list has one word per line, optionally followed by a tab and its count in some corpus
+//! (which is what `cb-vocab` writes). Word ids start from 1 in file order; id 0 is reserved for
+//! every unknown word.
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use errors::*;
+use farm::{self, FarmMap, new_farm};
+
+/// A list of known words, and their counts if the list had them
+pub struct Vocab {
+    words: Vec<String>,
+    counts: Option<Vec<u64>>,
+    ids: FarmMap<String, usize>,
+}
+
+impl Vocab {
+    /// Create a vocabulary from words (and counts, in the same order)
+    pub fn new(words: Vec<String>, counts: Option<Vec<u64>>) -> Vocab {
+        if let Some(ref counts) = counts {
+            assert_eq!(words.len(), counts.len(), "Every word needs exactly one count.");
+        }
+        let mut ids = new_farm();
+        for (id, word) in words.iter().enumerate() {
+            ids.insert(word.clone(), id + 1);
+        }
+        Vocab { words: words, counts: counts, ids: ids }
+    }
+
+    /// Read a word list, one word per line, each optionally followed by a tab and a count
+    ///
+    /// The counts are only kept if every line has one.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Vocab> {
+        let file = File::open(path.as_ref())
+            .map_err(|e| Error::MissingFile("word list", Some(e)))?;
+        let mut words = vec![];
+        let mut counts = Some(vec![]);
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let mut fields = line.splitn(2, '\t');
+            words.push(fields.next().unwrap_or("").to_owned());
+            let count = fields.next().and_then(|c| c.trim().parse::<u64>().ok());
+            counts = match (counts, count) {
+                (Some(mut counts), Some(count)) => { counts.push(count); Some(counts) },
+                _ => None
+            };
+        }
+        Ok(Vocab::new(words, counts))
+    }
+
+    /// Write the word list, with counts if there are any
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for (id, word) in self.words.iter().enumerate() {
+            match self.counts {
+                Some(ref counts) => writeln!(writer, "{}\t{}", word, counts[id])?,
+                None => writeln!(writer, "{}", word)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// How many words are known. Matrices need one more row for the unknown word.
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// The id of a word, which is 0 if it's unknown
+    pub fn id(&self, word: &str) -> usize {
+        *self.ids.get(word).unwrap_or(&0)
+    }
+
+    /// The word with this id, unless it is the unknown word (or out of range)
+    pub fn word(&self, id: usize) -> Option<&str> {
+        if id == 0 { None }
+        else { self.words.get(id - 1).map(|w| w.as_ref()) }
+    }
+
+    /// All the known words, in id order (starting from id 1)
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    /// How often the word with this id occurred, if the word list had counts
+    pub fn count(&self, id: usize) -> Option<u64> {
+        if id == 0 { return None; }
+        self.counts.as_ref().and_then(|counts| counts.get(id - 1).cloned())
+    }
+
+    /// The sum of all the counts, if the word list had counts
+    pub fn total(&self) -> Option<u64> {
+        self.counts.as_ref().map(|counts| counts.iter().sum())
+    }
+
+    /// Combine word lists into one with every word in any of them, or, for the `intersection`,
+    /// only the words in all of them
+    ///
+    /// Words are in the order they first appear, list by list. Counts are added up, if every list
+    /// has counts.
+    pub fn combine(vocabs: &[&Vocab], intersection: bool) -> Vocab {
+        let mut words: Vec<String> = vec![];
+        let mut counts: Vec<u64> = vec![];
+        let mut ids: FarmMap<String, usize> = new_farm();
+        for vocab in vocabs {
+            for (i, word) in vocab.words.iter().enumerate() {
+                let count = vocab.count(i + 1).unwrap_or(0);
+                if let Some(&id) = ids.get(word) {
+                    counts[id] += count;
+                    continue;
+                }
+                if intersection && !vocabs.iter().all(|other| other.id(word) != 0) {
+                    continue;
+                }
+                ids.insert(word.clone(), words.len());
+                words.push(word.clone());
+                counts.push(count);
+            }
+        }
+        let has_counts = vocabs.iter().all(|vocab| vocab.counts.is_some());
+        Vocab::new(words, if has_counts { Some(counts) } else { None })
+    }
+
+    /// The id in `other` of every word in this list, by id (so the first is the unknown word)
+    ///
+    /// Words that `other` doesn't have become its unknown word, 0.
+    pub fn ids_in(&self, other: &Vocab) -> Vec<usize> {
+        let mut ids = vec![0];
+        ids.extend(self.words.iter().map(|word| other.id(word)));
+        ids
+    }
+
+    /// A hash of the words in order, which tells whether two word lists give words the same ids
+    /// (the counts don't matter)
+    pub fn fingerprint(&self) -> String {
+        farm::fingerprint(&self.words)
+    }
+}
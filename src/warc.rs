@@ -1,8 +1,10 @@
 //! Web ARChive format parser
 //!
 //! This was originally warc-nom-parser.
-//! The main difference is that most headers are ignored, and none are returned.
+//! The main difference is that most headers are ignored, and only the record's ID and URI are
+//! returned with its payload.
 use std::{str, io, mem, ptr};
+use std::ops::Deref;
 use nom::{Offset, space, Needed, Consumer, ConsumerState, Input, Move, IResult, Producer};
 use std::io::{Read, StdinLock};
 const SIZE_LIMIT: usize = 1 << 20;
@@ -39,8 +41,25 @@ impl Iterator for WarcStreamer {
     }
 }
 
-/// Warc record (we only keep the payload)
-pub type Record = String;
+/// Warc record (we only keep the payload and what identifies it)
+///
+/// A record dereferences to its payload, so it can be used as a `&str`.
+#[derive(PartialEq,Eq,Debug,Clone)]
+pub struct Record {
+    /// The WARC-Record-ID header, if there was one
+    pub id: Option<String>,
+    /// The WARC-Target-URI header, if there was one
+    pub uri: Option<String>,
+    /// The payload
+    pub content: String,
+}
+
+impl Deref for Record {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.content
+    }
+}
 
 #[derive(PartialEq,Eq,Debug,Clone)]
 pub enum State {
@@ -225,9 +244,16 @@ pub fn record(input: &[u8]) -> IResult<&[u8], Record> {
                 return IResult::Incomplete(Needed::Size(length - i.len()));
             } else {
                 // It's already in the buffer
+                let header = |name| headers.iter()
+                    .find(|&&(k, _v)| k == name)
+                    .map(|&(_k, v)| v.trim().to_owned());
                 return IResult::Done(&i[length..], // slide forward
-                                     String::from_utf8_lossy(&i[0..length])
-                                             .into_owned());
+                                     Record {
+                                         id: header("WARC-Record-ID"),
+                                         uri: header("WARC-Target-URI"),
+                                         content: String::from_utf8_lossy(&i[0..length])
+                                             .into_owned(),
+                                     });
             }
         }
         IResult::Incomplete(a) => IResult::Incomplete(a),
@@ -0,0 +1,353 @@
+//! Context windows: which tokens are the context of each token, and how much they count
+//!
+//! Published methods weigh context words differently by their distance d from the center word:
+//! GloVe counts 1/d (harmonic), word2vec's randomly shrunk windows count (R - d + 1) / R in
+//! expectation (linear, for radius R), and others decay like a Gaussian.
+//!
+//! Contexts can also be told apart by where they are: on which side of the center word
+//! (directional), or at which offset (positional, as in structured skip-gram). Then every word
+//! has a context column for each side or offset, in blocks of one column per word.
+//!
+//! Windows can also be kept from reaching across paragraphs or sentences, by splitting documents
+//! into those first. Paragraphs are lines, which is how WET files lay out block elements, and
+//! sentences are found by the Unicode sentence boundary rules.
+use std::cmp::min;
+use std::str::FromStr;
+use unicode_segmentation::UnicodeSegmentation;
+use errors::*;
+
+/// How much a context word counts, by its distance from the center word
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Weighting {
+    /// Every context word counts 1
+    Uniform,
+    /// 1 / d, like GloVe
+    Harmonic,
+    /// (R - d + 1) / R for radius R, like word2vec
+    Linear,
+    /// exp(-d^2 / 2 sigma^2), with this sigma
+    Gaussian(f64),
+}
+
+impl Weighting {
+    /// Look up a weighting by name. Only the Gaussian weighting uses `sigma`.
+    pub fn from_name(name: &str, sigma: f64) -> Result<Weighting> {
+        match name {
+            "uniform" => Ok(Weighting::Uniform),
+            "harmonic" => Ok(Weighting::Harmonic),
+            "linear" => Ok(Weighting::Linear),
+            "gaussian" => Ok(Weighting::Gaussian(sigma)),
+            _ => Err(Error::Other(format!(
+                "There's no window weighting called {:?}. \
+                Try uniform, harmonic, linear or gaussian.", name)))
+        }
+    }
+
+    /// The weight of a word `distance` tokens from the center, on a side reaching `radius` tokens
+    ///
+    /// The center itself (distance 0) always weighs 1.
+    pub fn weight(&self, distance: usize, radius: usize) -> f64 {
+        if distance == 0 {
+            return 1.0;
+        }
+        let d = distance as f64;
+        match *self {
+            Weighting::Uniform => 1.0,
+            Weighting::Harmonic => 1.0 / d,
+            Weighting::Linear => (radius as f64 - d + 1.0) / radius as f64,
+            Weighting::Gaussian(sigma) => (-d * d / (2.0 * sigma * sigma)).exp(),
+        }
+    }
+
+    /// The weights of every distance from 0 to `radius`
+    fn weights(&self, radius: usize) -> Vec<f64> {
+        (0..radius + 1).map(|distance| self.weight(distance, radius)).collect()
+    }
+}
+
+/// Which context columns a context word is counted in
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Contexts {
+    /// One block: a word is the same context wherever it is in the window
+    Plain,
+    /// A block for words before the center and one for words after it
+    Directional,
+    /// A block for each offset from the center
+    Positional,
+}
+
+impl FromStr for Contexts {
+    type Err = Error;
+    fn from_str(name: &str) -> Result<Contexts> {
+        match name {
+            "plain" => Ok(Contexts::Plain),
+            "directional" => Ok(Contexts::Directional),
+            "positional" => Ok(Contexts::Positional),
+            _ => Err(Error::Other(format!(
+                "There are no contexts called {:?}. Try plain, directional or positional.", name)))
+        }
+    }
+}
+
+/// What windows never reach across
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Boundary {
+    Document,
+    Paragraph,
+    Sentence,
+}
+
+impl Boundary {
+    /// Split a document into pieces to form windows in separately
+    ///
+    /// Blank paragraphs and sentences are left out.
+    pub fn split<'t>(&self, document: &'t str) -> Vec<&'t str> {
+        let paragraphs = document.lines().filter(|line| !line.trim().is_empty());
+        match *self {
+            Boundary::Document => vec![document],
+            Boundary::Paragraph => paragraphs.collect(),
+            Boundary::Sentence => paragraphs
+                .flat_map(|paragraph| paragraph.split_sentence_bounds())
+                .filter(|sentence| !sentence.trim().is_empty())
+                .collect(),
+        }
+    }
+}
+
+impl FromStr for Boundary {
+    type Err = Error;
+    fn from_str(name: &str) -> Result<Boundary> {
+        match name {
+            "document" => Ok(Boundary::Document),
+            "paragraph" => Ok(Boundary::Paragraph),
+            "sentence" => Ok(Boundary::Sentence),
+            _ => Err(Error::Other(format!(
+                "There's no boundary called {:?}. Try document, paragraph or sentence.", name)))
+        }
+    }
+}
+
+/// A window sliding over a sequence of tokens, centered on each token in turn
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Window {
+    /// How many tokens before the center are context
+    pub left: usize,
+    /// How many tokens after the center are context
+    pub right: usize,
+    /// Whether the center token is its own context
+    pub include_self: bool,
+    /// How much each context token counts
+    pub weighting: Weighting,
+    /// Whether contexts on different sides or at different offsets are told apart
+    pub contexts: Contexts,
+    /// What the window never reaches across
+    pub boundary: Boundary,
+}
+
+impl Window {
+    /// A uniformly weighted window reaching `radius` tokens to either side, not including the
+    /// center
+    pub fn symmetric(radius: usize) -> Window {
+        Window {
+            left: radius,
+            right: radius,
+            include_self: false,
+            weighting: Weighting::Uniform,
+            contexts: Contexts::Plain,
+            boundary: Boundary::Document,
+        }
+    }
+
+    /// Names of the blocks of context columns, in order
+    ///
+    /// Positional blocks are named by their offset, like "-2" or "+1", and the center's own block
+    /// (only there with `include_self`) is "self".
+    pub fn blocks(&self) -> Vec<String> {
+        let center = if self.include_self { vec!["self".to_owned()] } else { vec![] };
+        match self.contexts {
+            Contexts::Plain => vec!["context".to_owned()],
+            Contexts::Directional => {
+                let mut blocks = vec!["left".to_owned()];
+                blocks.extend(center);
+                blocks.push("right".to_owned());
+                blocks
+            },
+            Contexts::Positional => {
+                let mut blocks: Vec<String> = (0..self.left)
+                    .map(|i| format!("-{}", self.left - i))
+                    .collect();
+                blocks.extend(center);
+                blocks.extend((1..self.right + 1).map(|offset| format!("+{}", offset)));
+                blocks
+            }
+        }
+    }
+
+    /// Which block of context columns the token at `context` is counted in, as a context of the
+    /// token at `center`
+    pub fn block(&self, center: usize, context: usize) -> usize {
+        let before = context < center;
+        match self.contexts {
+            Contexts::Plain => 0,
+            Contexts::Directional if before => 0,
+            Contexts::Directional if context == center => 1,
+            Contexts::Directional => if self.include_self { 2 } else { 1 },
+            Contexts::Positional if before => self.left - (center - context),
+            Contexts::Positional if context == center => self.left,
+            Contexts::Positional => self.left + context - center
+                - if self.include_self { 0 } else { 1 },
+        }
+    }
+
+    /// Call `pair(center, context, weight)` with the positions of every center and context
+    /// token, for a sequence of `len` tokens
+    ///
+    /// Windows are cut short at the ends of the sequence rather than wrapping around.
+    pub fn for_each_pair<F: FnMut(usize, usize, f64)>(&self, len: usize, mut pair: F) {
+        let left_weights = self.weighting.weights(self.left);
+        let right_weights = self.weighting.weights(self.right);
+        for center in 0..len {
+            let start = center.saturating_sub(self.left);
+            let end = min(len, center + self.right + 1);
+            for context in start..center {
+                pair(center, context, left_weights[center - context]);
+            }
+            if self.include_self {
+                pair(center, center, 1.0);
+            }
+            for context in center + 1..end {
+                pair(center, context, right_weights[context - center]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every (center, context, weight) the window gives over `len` tokens, in order
+    fn pairs(window: &Window, len: usize) -> Vec<(usize, usize, f64)> {
+        let mut pairs = vec![];
+        window.for_each_pair(len, |center, context, weight| pairs.push((center, context, weight)));
+        pairs
+    }
+
+    fn uniform(left: usize, right: usize, include_self: bool, contexts: Contexts) -> Window {
+        Window { left: left, right: right, include_self: include_self, contexts: contexts,
+            ..Window::symmetric(0) }
+    }
+
+    #[test]
+    fn symmetric_pairs_are_cut_at_the_ends() {
+        let window = Window::symmetric(1);
+        assert_eq!(pairs(&window, 3), vec![
+            (0, 1, 1.0),
+            (1, 0, 1.0), (1, 2, 1.0),
+            (2, 1, 1.0),
+        ]);
+        assert!(pairs(&window, 0).is_empty());
+        assert!(pairs(&window, 1).is_empty());
+    }
+
+    #[test]
+    fn asymmetric_radii() {
+        let window = uniform(2, 0, false, Contexts::Plain);
+        assert_eq!(pairs(&window, 3), vec![
+            (1, 0, 1.0),
+            (2, 0, 1.0), (2, 1, 1.0),
+        ]);
+        let window = uniform(0, 1, false, Contexts::Plain);
+        assert_eq!(pairs(&window, 3), vec![(0, 1, 1.0), (1, 2, 1.0)]);
+    }
+
+    #[test]
+    fn self_pairs() {
+        let window = uniform(1, 1, true, Contexts::Plain);
+        assert_eq!(pairs(&window, 2), vec![
+            (0, 0, 1.0), (0, 1, 1.0),
+            (1, 0, 1.0), (1, 1, 1.0),
+        ]);
+        // Even a window of radius 0 has the center itself
+        assert_eq!(pairs(&uniform(0, 0, true, Contexts::Plain), 2), vec![(0, 0, 1.0), (1, 1, 1.0)]);
+        assert!(pairs(&uniform(0, 0, false, Contexts::Plain), 2).is_empty());
+    }
+
+    #[test]
+    fn weighting() {
+        let mut window = Window::symmetric(2);
+        window.weighting = Weighting::Harmonic;
+        assert_eq!(pairs(&window, 5).into_iter().filter(|&(center, _, _)| center == 2)
+            .collect::<Vec<_>>(),
+            vec![(2, 0, 0.5), (2, 1, 1.0), (2, 3, 1.0), (2, 4, 0.5)]);
+        // Linear weights shrink towards the end of each side, by that side's radius
+        let mut window = uniform(2, 1, false, Contexts::Plain);
+        window.weighting = Weighting::Linear;
+        assert_eq!(pairs(&window, 4).into_iter().filter(|&(center, _, _)| center == 2)
+            .collect::<Vec<_>>(),
+            vec![(2, 0, 0.5), (2, 1, 1.0), (2, 3, 1.0)]);
+        // The center always weighs 1
+        window.include_self = true;
+        window.weighting = Weighting::Gaussian(0.5);
+        assert!(pairs(&window, 3).contains(&(1, 1, 1.0)));
+        assert_eq!(Weighting::Gaussian(1.0).weight(2, 5), (-2.0f64).exp());
+        assert_eq!(Weighting::Uniform.weight(7, 3), 1.0);
+    }
+
+    #[test]
+    fn plain_blocks() {
+        let window = uniform(2, 2, true, Contexts::Plain);
+        assert_eq!(window.blocks(), vec!["context"]);
+        for &(center, context, _) in pairs(&window, 5).iter() {
+            assert_eq!(window.block(center, context), 0);
+        }
+    }
+
+    #[test]
+    fn directional_blocks() {
+        let window = uniform(2, 1, false, Contexts::Directional);
+        assert_eq!(window.blocks(), vec!["left", "right"]);
+        assert_eq!(window.block(3, 1), 0);
+        assert_eq!(window.block(3, 2), 0);
+        assert_eq!(window.block(3, 4), 1);
+
+        let window = uniform(2, 1, true, Contexts::Directional);
+        assert_eq!(window.blocks(), vec!["left", "self", "right"]);
+        assert_eq!(window.block(3, 2), 0);
+        assert_eq!(window.block(3, 3), 1);
+        assert_eq!(window.block(3, 4), 2);
+    }
+
+    #[test]
+    fn positional_blocks() {
+        let window = uniform(2, 1, false, Contexts::Positional);
+        assert_eq!(window.blocks(), vec!["-2", "-1", "+1"]);
+        assert_eq!(window.block(5, 3), 0);
+        assert_eq!(window.block(5, 4), 1);
+        assert_eq!(window.block(5, 6), 2);
+
+        let window = uniform(1, 2, true, Contexts::Positional);
+        assert_eq!(window.blocks(), vec!["-1", "self", "+1", "+2"]);
+        assert_eq!(window.block(5, 4), 0);
+        assert_eq!(window.block(5, 5), 1);
+        assert_eq!(window.block(5, 6), 2);
+        assert_eq!(window.block(5, 7), 3);
+    }
+
+    #[test]
+    fn every_pair_has_a_block() {
+        for &contexts in [Contexts::Plain, Contexts::Directional, Contexts::Positional].iter() {
+            for &include_self in [false, true].iter() {
+                let window = uniform(3, 2, include_self, contexts);
+                let blocks = window.blocks().len();
+                let mut used = vec![false; blocks];
+                for &(center, context, _) in pairs(&window, 8).iter() {
+                    let block = window.block(center, context);
+                    assert!(block < blocks, "{:?}: block {} of {}", window, block, blocks);
+                    used[block] = true;
+                }
+                assert!(used.iter().all(|&used| used), "{:?} leaves blocks empty", window);
+            }
+        }
+    }
+}